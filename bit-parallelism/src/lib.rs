@@ -14,9 +14,9 @@
 //! The central idea behind the algorithms in this library  is this:
 //!
 //! * If you have a bunch of small integers — each smaller that sixty four bits, e.g. a bunch of bytes, 
-//! we can pack many of them into a single sixty four bit integer.
+//!   we can pack many of them into a single sixty four bit integer.
 //! * We can then operate on that packed integer as if it were a single number. For example, 
-//! we can fit 8 byte sized numbers in a single word.
+//!   we can fit 8 byte sized numbers in a single word.
 //! * By operating on the packed integer, we are in effect operating on 8 different integers in parallel.
 //!
 //! This is what is called `world level parallelism`.
@@ -91,6 +91,10 @@
 //! 6. [This StackOverflow Question. Scroll down until you find the answer by user `templatetypedef`](<https://stackoverflow.com/questions/3878320/understanding-fusion-trees>)
 //!
 
+// The bit masks and multipliers in this crate are grouped to mirror the packed
+// fields (and spacer bits) they operate on, not into nibbles.
+#![allow(clippy::unusual_byte_groupings)]
+
 // Test that pointer width is compatible. This asserts that usize is 64 bits,
 // which a lot of algorithms in this crate currently assume.
 #[cfg(not(any(target_pointer_width = "64",)))]
@@ -0,0 +1,191 @@
+//! Building a Cartesian Tree Online
+//!
+//! The stack based construction adds the values of the array one at a
+//! time, and never looks back at a value once it leaves the right spine.
+//! A [`CartesianTreeBuilder`] keeps the state of that construction between
+//! calls, so we can feed it values as they arrive, and only pay for the
+//! work that each new value causes.
+//!
+//! While the builder is still growing, we cannot use the lowest common
+//! ancestor structure that a finished tree uses for range min queries,
+//! since every push may reshape the right spine. Instead, we remember, for
+//! every value, the value right below it on the stack when we pushed it.
+//! Starting at `j` and following these links walks over the values that
+//! are the minimum of some range ending at `j`, from right to left. The
+//! minimum of `[i, j]` is thus the last value on that chain that is not
+//! before `i`. To walk these chains quickly, every value also keeps a jump
+//! pointer further down its chain. Jump pointers laid out in the skew-binary
+//! pattern of [Myers](https://doi.org/10.1016/0020-0190(83)90106-0) can be
+//! set up in constant time per push, and let us find the end of the walk
+//! in `O(lg n)` steps.
+
+use crate::tree::{
+    CartesianNodeIdx, CartesianTree, CartesianTreeNode, Nodes, OwnedCartesianTree, Stack, TieBreak,
+};
+
+/// Builds a cartesian tree one value at a time, and answers range
+/// min queries over the values seen so far.
+///
+/// ```rust
+/// use cartesian_tree::CartesianTreeBuilder;
+///
+/// let mut builder = CartesianTreeBuilder::new();
+/// for value in [93, 84, 33, 64, 62] {
+///     builder.push(value);
+/// }
+/// assert_eq!(builder.current_root(), Some((2, &33)));
+/// assert_eq!(builder.range_min(3, 4), (4, &62));
+/// builder.push(12);
+/// assert_eq!(builder.build().root().unwrap().array_index(), 5);
+/// ```
+#[derive(Debug)]
+pub struct CartesianTreeBuilder<T> {
+    values: Vec<T>,
+    nodes: Nodes,
+
+    /// The right spine of the tree
+    stack: Stack,
+
+    /// For each value, the value right below it on the stack when we
+    /// pushed it, the number of links in the chain below it, and the
+    /// value further down the chain that its jump pointer leads to
+    below: Vec<Option<usize>>,
+    chain_len: Vec<usize>,
+    jump: Vec<usize>,
+
+    tie_break: TieBreak,
+}
+
+impl<T: Ord> Default for CartesianTreeBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Extend<T> for CartesianTreeBuilder<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        values.into_iter().for_each(|value| {
+            self.push(value);
+        });
+    }
+}
+
+impl<T: Ord> CartesianTreeBuilder<T> {
+    /// A builder that resolves equal values like [`CartesianTree::from`]
+    pub fn new() -> Self {
+        Self::with_tie_break(TieBreak::default())
+    }
+
+    /// A builder that resolves equal values according to the given policy
+    pub fn with_tie_break(tie_break: TieBreak) -> Self {
+        CartesianTreeBuilder {
+            values: Vec::new(),
+            nodes: Vec::new(),
+            stack: Vec::new(),
+            below: Vec::new(),
+            chain_len: Vec::new(),
+            jump: Vec::new(),
+            tie_break,
+        }
+    }
+
+    /// Adds the next value to the tree, and returns its index.
+    /// This takes amortized constant time.
+    pub fn push(&mut self, value: T) -> usize {
+        let idx = self.values.len();
+        self.values.push(value);
+        self.nodes.push(CartesianTreeNode::default());
+        let (values, tie_break) = (&self.values, self.tie_break);
+        CartesianTree::<T>::add_node_to_cartesian_tree(
+            &mut self.nodes,
+            &mut self.stack,
+            CartesianNodeIdx::new(idx),
+            |top, new| tie_break.earlier_wins(values[top].cmp(&values[new])),
+        );
+
+        // The new value sits right above its parent on the stack
+        let below = self.nodes[idx]
+            .parent_idx
+            .as_ref()
+            .map(|parent| parent.get());
+        let (chain_len, jump) = match below {
+            None => (0, idx),
+            Some(below) => {
+                // Jump twice as far as our parent does when its jump
+                // and the one after it cover the same distance
+                let far = self.jump[below];
+                let jump = if self.chain_len[below] - self.chain_len[far]
+                    == self.chain_len[far] - self.chain_len[self.jump[far]]
+                {
+                    self.jump[far]
+                } else {
+                    below
+                };
+                (self.chain_len[below] + 1, jump)
+            }
+        };
+        self.below.push(below);
+        self.chain_len.push(chain_len);
+        self.jump.push(jump);
+        idx
+    }
+
+    /// The number of values pushed so far
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The values pushed so far
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// The root of the tree of the values pushed so far, which is
+    /// their minimum. Returns its index along with a reference to it,
+    /// or `None` if we have not pushed any values yet.
+    pub fn current_root(&self) -> Option<(usize, &T)> {
+        self.stack
+            .first()
+            .map(|root| (root.get(), &self.values[root.get()]))
+    }
+
+    /// Finds the minimum value in the inclusive range `[i, j]` of the
+    /// values pushed so far. Returns its index along with a reference to
+    /// it. We report the same minimum as [`CartesianTree::range_min`]
+    /// would on the finished tree. This takes `O(lg n)` time.
+    ///
+    /// Panics if either `i` or `j` is out of bounds.
+    pub fn range_min(&self, i: usize, j: usize) -> (usize, &T) {
+        let len = self.values.len();
+        assert!(
+            i < len && j < len,
+            "indexes {} and {} are out of bounds for {} values",
+            i,
+            j,
+            len
+        );
+        let (first, mut cur) = if i <= j { (i, j) } else { (j, i) };
+        while let Some(below) = self.below[cur].filter(|&below| below >= first) {
+            // The chain only ever moves to the left, so if the jump
+            // lands in the range, so does everything it skips over
+            cur = if self.jump[cur] >= first {
+                self.jump[cur]
+            } else {
+                below
+            };
+        }
+        (cur, &self.values[cur])
+    }
+
+    /// Finishes the tree. This only closes off the right spine. As with
+    /// any tree, the first range min query builds the lowest common
+    /// ancestor structure, in linear time. The builder has no bound on the
+    /// number of values it takes, so the tree always has `usize` indexes.
+    pub fn build(self) -> OwnedCartesianTree<T> {
+        OwnedCartesianTree::from_linked_nodes(self.values, self.nodes, &self.stack)
+    }
+}
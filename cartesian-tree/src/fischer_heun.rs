@@ -0,0 +1,172 @@
+//! Fischer-Heun RMQ
+//!
+//! A `<O(n), O(1)>` solution to the range min query problem. We split the
+//! array into blocks of size `b = lg(n) / 4` and build a sparse table over
+//! the minimum of each block. A query that spans several blocks is then
+//! answered by one sparse table lookup and two in-block lookups.
+//!
+//! To answer in-block queries in constant time, we precompute, for each
+//! block, a table holding the answer to every query that falls within the
+//! block. Building one such table per block would take `O(n b)` time, so we
+//! instead observe that two blocks whose cartesian trees are isomorphic have
+//! their minima at the same positions for every range. Such blocks have the
+//! same cartesian tree number, and can thus share a single table. There are
+//! at most `4^b = sqrt(n)` different cartesian tree numbers, which keeps the
+//! total cost of building the in-block tables sub-linear.
+
+use crate::sparse_table::{floor_lg, min_of};
+use crate::tree::{cartesian_tree_number_of, TieBreak};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A range min query structure with linear preprocessing time
+/// and constant query time.
+#[derive(Debug)]
+pub struct FischerHeunRmq<'a, T: Ord> {
+    /// The array over which we answer queries
+    underlying: &'a [T],
+
+    /// The number of items in every block but, possibly, the last one
+    block_size: usize,
+
+    /// `sparse_table[k][i]` is the index of the minimum value in the
+    /// `2^k` blocks starting at block `i`
+    sparse_table: Vec<Vec<usize>>,
+
+    /// For each block, the location of its in-block table in `in_block_tables`
+    block_table_idx: Vec<usize>,
+
+    /// In-block lookup tables, shared by blocks with the same cartesian tree
+    /// number. Entry `i * block_size + j` holds the offset of the minimum
+    /// value in the inclusive range `[i, j]` of the block.
+    in_block_tables: Vec<Vec<u8>>,
+
+    /// Decides which of several equal minima we report
+    tie_break: TieBreak,
+}
+
+impl<'a, T: Ord> From<&'a [T]> for FischerHeunRmq<'a, T> {
+    fn from(underlying: &'a [T]) -> Self {
+        Self::with_tie_break(underlying, TieBreak::default())
+    }
+}
+
+impl<'a, T: Ord> FischerHeunRmq<'a, T> {
+    /// Builds the RMQ structure, resolving duplicate minima according
+    /// to the given policy. The cartesian tree numbers of the blocks
+    /// are computed with the same policy, so that blocks that share
+    /// an in-block table agree on the location of every minimum.
+    pub fn with_tie_break(underlying: &'a [T], tie_break: TieBreak) -> Self {
+        let len = underlying.len();
+        let lg_len = if len < 2 { 0 } else { floor_lg(len) };
+        let block_size = (lg_len / 4).max(1);
+
+        let mut block_mins = Vec::with_capacity(len / block_size + 1);
+        let mut block_table_idx = Vec::with_capacity(len / block_size + 1);
+        let mut in_block_tables = Vec::new();
+        let mut tables_by_number = HashMap::new();
+        for (block_idx, block) in underlying.chunks(block_size).enumerate() {
+            let number = cartesian_tree_number_of(block, tie_break);
+            let table_idx = *tables_by_number.entry(number).or_insert_with(|| {
+                in_block_tables.push(Self::build_in_block_table(block, block_size, tie_break));
+                in_block_tables.len() - 1
+            });
+            let table = &in_block_tables[table_idx];
+            let min_offset = table[block.len() - 1] as usize;
+            block_mins.push(block_idx * block_size + min_offset);
+            block_table_idx.push(table_idx);
+        }
+        let sparse_table = Self::build_sparse_table(underlying, tie_break, block_mins);
+        FischerHeunRmq {
+            underlying,
+            block_size,
+            sparse_table,
+            block_table_idx,
+            in_block_tables,
+            tie_break,
+        }
+    }
+
+    /// Returns the index of the minimum value in the given range of the
+    /// underlying array. When the minimum occurs several times, the
+    /// [`TieBreak`] policy decides which occurrence we return. By default,
+    /// this is the rightmost one, just like [`crate::CartesianTree`].
+    ///
+    /// Panics if the range is empty or out of bounds.
+    pub fn query(&self, range: Range<usize>) -> usize {
+        assert!(
+            range.start < range.end && range.end <= self.underlying.len(),
+            "range {:?} is empty or out of bounds for an array of length {}",
+            range,
+            self.underlying.len()
+        );
+        let (first, last) = (range.start, range.end - 1);
+        let (first_block, last_block) = (first / self.block_size, last / self.block_size);
+        if first_block == last_block {
+            return self.in_block_query(first_block, first, last);
+        }
+        let block_end = (first_block + 1) * self.block_size - 1;
+        let mut min_idx = self.in_block_query(first_block, first, block_end);
+        if first_block + 1 < last_block {
+            let middle = self.sparse_query(first_block + 1, last_block - 1);
+            min_idx = self.min_of(min_idx, middle);
+        }
+        let block_start = last_block * self.block_size;
+        let last_min = self.in_block_query(last_block, block_start, last);
+        self.min_of(min_idx, last_min)
+    }
+
+    /// Finds the minimum in the inclusive range `[first, last]`, both of
+    /// which lie in the block with the given index.
+    fn in_block_query(&self, block_idx: usize, first: usize, last: usize) -> usize {
+        let block_start = block_idx * self.block_size;
+        let table = &self.in_block_tables[self.block_table_idx[block_idx]];
+        let (i, j) = (first - block_start, last - block_start);
+        block_start + table[i * self.block_size + j] as usize
+    }
+
+    /// Finds the minimum across the blocks in the inclusive range `[first, last]`
+    fn sparse_query(&self, first: usize, last: usize) -> usize {
+        let k = floor_lg(last - first + 1);
+        let left = self.sparse_table[k][first];
+        let right = self.sparse_table[k][last + 1 - (1 << k)];
+        self.min_of(left, right)
+    }
+
+    fn min_of(&self, left: usize, right: usize) -> usize {
+        min_of(self.underlying, self.tie_break, left, right)
+    }
+
+    fn build_in_block_table(block: &[T], block_size: usize, tie_break: TieBreak) -> Vec<u8> {
+        let mut table = vec![0; block_size * block_size];
+        for i in 0..block.len() {
+            let mut min_offset = i;
+            for j in i..block.len() {
+                if !tie_break.earlier_wins(block[min_offset].cmp(&block[j])) {
+                    min_offset = j;
+                }
+                table[i * block_size + j] = min_offset as u8;
+            }
+        }
+        table
+    }
+
+    fn build_sparse_table(
+        underlying: &[T],
+        tie_break: TieBreak,
+        block_mins: Vec<usize>,
+    ) -> Vec<Vec<usize>> {
+        let num_blocks = block_mins.len();
+        let mut sparse_table = vec![block_mins];
+        let mut window = 1;
+        while 2 * window <= num_blocks {
+            let prev = sparse_table.last().unwrap();
+            let next = (0..=num_blocks - 2 * window)
+                .map(|i| min_of(underlying, tie_break, prev[i], prev[i + window]))
+                .collect();
+            sparse_table.push(next);
+            window *= 2;
+        }
+        sparse_table
+    }
+}
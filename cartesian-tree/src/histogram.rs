@@ -0,0 +1,80 @@
+//! Largest Rectangle in a Histogram
+//!
+//! Given the heights of the bars of a histogram, we want the rectangle
+//! with the largest area that fits under the bars. The top of such a
+//! rectangle touches its shortest bar, and the rectangle extends as far
+//! as it can on either side of that bar. That is, it spans exactly the
+//! range in which the shortest bar is the minimum, which is the range
+//! spanned by its subtree in the cartesian tree of the heights. We thus
+//! only have to consider one rectangle per bar.
+//!
+//! We do not need the rest of the tree to find these ranges. The range
+//! of a bar ends right before the nearest shorter bar on either side,
+//! which one pass of a stack finds for every bar at once.
+
+use crate::nearest::{nearest_left_by, nearest_right_by};
+use std::ops::Range;
+
+/// The heights of histogram bars, which we multiply by widths
+/// to get areas. It is implemented for the unsigned integers of
+/// at most 64 bits, since the product of two of those always
+/// fits in a `u128`.
+pub trait Height: Ord + Copy {
+    /// This height, widened so that an area never overflows
+    fn to_u128(self) -> u128;
+}
+
+macro_rules! impl_height {
+    ($($t:ty),*) => {
+        $(impl Height for $t {
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+        })*
+    };
+}
+
+impl_height!(u8, u16, u32, u64, usize);
+
+/// A rectangle that fits under the bars of a histogram
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rectangle<T> {
+    /// The bars that the rectangle covers
+    pub span: Range<usize>,
+
+    /// The height of the shortest of those bars, which
+    /// is the height of the rectangle, and its area
+    pub height: T,
+    pub area: u128,
+}
+
+/// Finds the rectangle with the largest area that fits under
+/// the bars with the given heights. When there are several such
+/// rectangles, we return the one whose shortest bar comes first.
+/// Returns `None` when there are no bars.
+///
+/// ```rust
+/// use cartesian_tree::histogram::largest_rectangle;
+///
+/// let rectangle = largest_rectangle(&[2u32, 1, 5, 6, 2, 3]).unwrap();
+/// assert_eq!(rectangle.span, 2..4);
+/// assert_eq!(rectangle.area, 10);
+/// ```
+pub fn largest_rectangle<T>(heights: &[T]) -> Option<Rectangle<T>>
+where
+    T: Height,
+{
+    // Of two equal bars, the later one is the minimum of the range they
+    // share, so a range stops at an equal bar on its right only
+    let left = nearest_left_by(heights, |left, bar| left < bar);
+    let right = nearest_right_by(heights, |right, bar| right <= bar);
+    let mut largest: Option<Rectangle<T>> = None;
+    for (i, &height) in heights.iter().enumerate() {
+        let span = left[i].map_or(0, |j| j + 1)..right[i].unwrap_or(heights.len());
+        let area = height.to_u128() * span.len() as u128;
+        if largest.as_ref().map_or(true, |largest| area > largest.area) {
+            largest = Some(Rectangle { span, height, area });
+        }
+    }
+    largest
+}
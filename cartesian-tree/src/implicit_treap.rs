@@ -0,0 +1,349 @@
+//! Implicit Treap
+//!
+//! A dynamic array that supports inserting, removing, splitting and
+//! concatenating in expected `O(lg n)` time, while still answering range
+//! min queries. It is a [`crate::Treap`] whose keys are implicit: the key of
+//! a node is its position in the array, which is the number of nodes that
+//! come before it in order. We never store these positions, since a single
+//! insertion would shift all of them. Instead, every node records the size
+//! of its subtree, which tells us how many nodes come before it within
+//! that subtree. Every node also records where the minimum of its subtree
+//! is, so a range min query only needs to look at `O(lg n)` nodes.
+//!
+//! A node keeps its value behind an [`Arc`], and shares it with every node
+//! whose subtree minimum it is. That way, recomputing the minimum of a
+//! subtree after its children change takes constant time, without copying
+//! any values, and the values do not need to implement `Clone`. This does
+//! cost an extra allocation per item, and an atomic reference count update
+//! whenever we recompute a minimum. It also means that the nodes of a
+//! treap share its values, so an [`ImplicitTreap`] can only be sent to
+//! another thread when its values are both `Send` and `Sync`.
+//!
+//! Building the treap out of an existing array takes linear time. We draw a
+//! priority for each item, and build the [`crate::CartesianTree`] of the
+//! priorities, whose shape the treap takes on.
+
+use crate::treap_node::{self, link_by_priority, merge, size, Entry, Priorities, TreapNode};
+use crate::tree::TieBreak;
+use std::sync::Arc;
+
+type Link<T> = treap_node::Link<Item<T>>;
+
+#[derive(Debug)]
+struct Item<T> {
+    value: Arc<T>,
+
+    /// The minimum of the subtree rooted at this node, and its
+    /// position relative to the start of the subtree
+    min: Arc<T>,
+    min_offset: usize,
+}
+
+impl<T> Item<T> {
+    fn new(value: T) -> Self {
+        let value = Arc::new(value);
+        Item {
+            min: Arc::clone(&value),
+            value,
+            min_offset: 0,
+        }
+    }
+}
+
+impl<T: Ord> Entry for Item<T> {
+    fn update(node: &mut TreapNode<Self>) {
+        let left_size = size(&node.left);
+        let (mut min, mut min_offset) = (&node.entry.value, left_size);
+        if let Some(left) = &node.left {
+            if TIE_BREAK.earlier_wins(left.entry.min.cmp(min)) {
+                min = &left.entry.min;
+                min_offset = left.entry.min_offset;
+            }
+        }
+        if let Some(right) = &node.right {
+            if !TIE_BREAK.earlier_wins(min.cmp(&right.entry.min)) {
+                min = &right.entry.min;
+                min_offset = left_size + 1 + right.entry.min_offset;
+            }
+        }
+        node.entry.min = Arc::clone(min);
+        node.entry.min_offset = min_offset;
+    }
+}
+
+/// Like [`crate::CartesianTree::from`], we report the rightmost of equal minima
+const TIE_BREAK: TieBreak = TieBreak::Rightmost;
+
+/// A dynamic array that answers range min queries. Since its nodes share
+/// their values through [`Arc`]s, it is `Send` only when `T: Send + Sync`.
+#[derive(Debug)]
+pub struct ImplicitTreap<T> {
+    root: Link<T>,
+    priorities: Priorities,
+}
+
+/// Copies the values into new nodes with the same priorities,
+/// so the copy has the same shape
+impl<T: Ord + Clone> Clone for ImplicitTreap<T> {
+    fn clone(&self) -> Self {
+        let nodes = treap_node::Nodes::new(&self.root)
+            .map(|node| TreapNode::new(Item::new((*node.entry.value).clone()), node.priority))
+            .collect();
+        ImplicitTreap {
+            root: link_by_priority(nodes),
+            priorities: self.priorities.clone(),
+        }
+    }
+}
+
+impl<T: Ord> Default for ImplicitTreap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> From<Vec<T>> for ImplicitTreap<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+/// Builds the treap in linear time
+impl<T: Ord> FromIterator<T> for ImplicitTreap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut priorities = Priorities::new();
+        let nodes = values
+            .into_iter()
+            .map(|value| TreapNode::new(Item::new(value), priorities.next()))
+            .collect();
+        ImplicitTreap {
+            root: link_by_priority(nodes),
+            priorities,
+        }
+    }
+}
+
+impl<T: Ord> ImplicitTreap<T> {
+    /// An empty array with randomly seeded priorities
+    pub fn new() -> Self {
+        ImplicitTreap {
+            root: None,
+            priorities: Priorities::new(),
+        }
+    }
+
+    /// An empty array whose priorities are drawn from a
+    /// generator with the given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        ImplicitTreap {
+            root: None,
+            priorities: Priorities::with_seed(seed),
+        }
+    }
+
+    /// The number of items in the array
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// The item at the given position, if there is one
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut cur = self.root.as_ref();
+        while let Some(node) = cur {
+            let left_size = size(&node.left);
+            if index < left_size {
+                cur = node.left.as_ref();
+            } else if index == left_size {
+                return Some(&node.entry.value);
+            } else {
+                index -= left_size + 1;
+                cur = node.right.as_ref();
+            }
+        }
+        None
+    }
+
+    /// Inserts an item at the given position, shifting
+    /// all the items after it one position to the right.
+    ///
+    /// Panics if `index` is larger than the length of the array.
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(
+            index <= self.len(),
+            "insertion index {} is out of bounds for an array of length {}",
+            index,
+            self.len()
+        );
+        let node = TreapNode::new(Item::new(value), self.priorities.next());
+        let (before, after) = split(self.root.take(), index);
+        self.root = merge(merge(before, Some(node)), after);
+    }
+
+    /// Adds an item to the end of the array
+    pub fn push(&mut self, value: T) {
+        self.insert(self.len(), value);
+    }
+
+    /// Removes and returns the item at the given position, shifting
+    /// all the items after it one position to the left.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(
+            index < self.len(),
+            "removal index {} is out of bounds for an array of length {}",
+            index,
+            self.len()
+        );
+        let (before, rest) = split(self.root.take(), index);
+        let (removed, after) = split(rest, 1);
+        self.root = merge(before, after);
+
+        // The removed node is a treap of its own now, so
+        // no other node shares its value as a minimum
+        let Item { value, min, .. } = removed.unwrap().entry;
+        drop(min);
+        match Arc::try_unwrap(value) {
+            Ok(value) => value,
+            Err(_) => unreachable!("the value of a removed node is not shared"),
+        }
+    }
+
+    /// Splits the array in two. Afterwards, `self` holds the
+    /// items in `[0, at)`, and we return the items in `[at, len)`.
+    ///
+    /// Panics if `at` is larger than the length of the array.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.len(),
+            "split index {} is out of bounds for an array of length {}",
+            at,
+            self.len()
+        );
+        let (before, after) = split(self.root.take(), at);
+        self.root = before;
+        ImplicitTreap {
+            root: after,
+            priorities: Priorities::with_seed(self.priorities.next()),
+        }
+    }
+
+    /// Moves all the items of `other` to the end of this array
+    pub fn append(&mut self, other: Self) {
+        self.root = merge(self.root.take(), other.root);
+    }
+
+    /// Finds the minimum value in the inclusive range `[i, j]` of the
+    /// array. Returns its position along with a reference to it. As with
+    /// [`crate::CartesianTree::range_min`], we report the rightmost of
+    /// several equal minima. This takes expected `O(lg n)` time.
+    ///
+    /// Panics if either `i` or `j` is out of bounds.
+    pub fn range_min(&self, i: usize, j: usize) -> (usize, &T) {
+        let len = self.len();
+        assert!(
+            i < len && j < len,
+            "indexes {} and {} are out of bounds for an array of length {}",
+            i,
+            j,
+            len
+        );
+        let (first, last) = if i <= j { (i, j) } else { (j, i) };
+        range_min(&self.root, first, last + 1).unwrap()
+    }
+
+    /// Iterates over the items of the array in order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            nodes: treap_node::Nodes::new(&self.root),
+        }
+    }
+}
+
+/// Cuts the treap rooted at `link` into its first `count` nodes and the rest
+fn split<T: Ord>(link: Link<T>, count: usize) -> (Link<T>, Link<T>) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            let left_size = size(&node.left);
+            if count <= left_size {
+                let (before, after) = split(node.left.take(), count);
+                node.left = after;
+                node.update();
+                (before, Some(node))
+            } else {
+                let (before, after) = split(node.right.take(), count - left_size - 1);
+                node.right = before;
+                node.update();
+                (Some(node), after)
+            }
+        }
+    }
+}
+
+/// Finds the minimum in the range `[start, end)` of the subtree rooted at
+/// `link`, where positions are relative to the start of the subtree. We only
+/// descend into a child when the range covers part of it, so we visit at
+/// most two paths from the root down.
+fn range_min<T: Ord>(link: &Link<T>, start: usize, end: usize) -> Option<(usize, &T)> {
+    let node = link.as_ref()?;
+    if start >= end {
+        return None;
+    }
+    if start == 0 && end >= node.size {
+        return Some((node.entry.min_offset, &node.entry.min));
+    }
+    let left_size = size(&node.left);
+    let mut min = None;
+    if start < left_size {
+        min = range_min(&node.left, start, end.min(left_size));
+    }
+    if start <= left_size && left_size < end {
+        min = min_of(min, (left_size, &node.entry.value));
+    }
+    if end > left_size + 1 {
+        let right_start = start.saturating_sub(left_size + 1);
+        let right_min = range_min(&node.right, right_start, end - left_size - 1);
+        if let Some((offset, value)) = right_min {
+            min = min_of(min, (left_size + 1 + offset, value));
+        }
+    }
+    min
+}
+
+/// Picks the smaller of an earlier candidate minimum and a later one
+fn min_of<'t, T: Ord>(
+    earlier: Option<(usize, &'t T)>,
+    later: (usize, &'t T),
+) -> Option<(usize, &'t T)> {
+    match earlier {
+        Some(earlier) if TIE_BREAK.earlier_wins(earlier.1.cmp(later.1)) => Some(earlier),
+        _ => Some(later),
+    }
+}
+
+/// Walks the array in order
+pub struct Iter<'t, T> {
+    nodes: treap_node::Nodes<'t, Item<T>>,
+}
+
+impl<'t, T> Iterator for Iter<'t, T> {
+    type Item = &'t T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| &*node.entry.value)
+    }
+}
+
+impl<'t, T: Ord> IntoIterator for &'t ImplicitTreap<T> {
+    type Item = &'t T;
+    type IntoIter = Iter<'t, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,122 @@
+//! Node Index Widths
+//!
+//! The nodes of a cartesian tree refer to each other by their indexes in the
+//! node arena, and record the range of the array that their subtrees span.
+//! By default, all of these are `usize`s. A tree over an array of at most
+//! `2^31` values can make do with `u32`s instead, which halves the size of
+//! the arena, and of the lowest common ancestor structure, on 64 bit
+//! platforms. The structure records positions in the Euler tour of the
+//! tree, which visits `2n - 1` nodes, so these must fit in the index type
+//! as well.
+//!
+//! A link to another node holds one more than the index of that node, as a
+//! non zero integer. This way, the compiler can represent a missing link as
+//! zero, and an `Option` of a link takes no more room than the link itself.
+
+use std::fmt;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+
+/// An unsigned integer type that can index the nodes of a
+/// cartesian tree. This is implemented for `u32`, `u64` and `usize`.
+pub trait NodeIndex: private::Sealed {}
+
+mod private {
+    use std::fmt::Debug;
+
+    pub trait Sealed: Copy + Debug + Default + Eq + Ord + Send + Sync + Serde {
+        /// The non zero counterpart of the index type
+        type Link: Copy + Debug + Eq + Ord + Send + Sync + Serde;
+
+        /// The largest number of nodes that indexes of this type can address,
+        /// leaving room for positions in the Euler tour of the tree
+        fn max_len() -> usize;
+
+        fn from_usize(value: usize) -> Self;
+        fn to_usize(self) -> usize;
+
+        /// Converts a node index into a link, and back
+        fn link(idx: usize) -> Self::Link;
+        fn unlink(link: Self::Link) -> usize;
+    }
+
+    /// With the `serde` feature, trees save their indexes
+    #[cfg(feature = "serde")]
+    pub trait Serde: serde::Serialize + serde::de::DeserializeOwned {}
+
+    #[cfg(feature = "serde")]
+    impl<T: serde::Serialize + serde::de::DeserializeOwned> Serde for T {}
+
+    #[cfg(not(feature = "serde"))]
+    pub trait Serde {}
+
+    #[cfg(not(feature = "serde"))]
+    impl<T> Serde for T {}
+}
+
+macro_rules! node_index {
+    ($index:ty, $link:ty) => {
+        impl NodeIndex for $index {}
+
+        impl private::Sealed for $index {
+            type Link = $link;
+
+            fn max_len() -> usize {
+                usize::try_from(<$index>::MAX / 2 + 1).unwrap_or(usize::MAX)
+            }
+
+            fn from_usize(value: usize) -> Self {
+                value as $index
+            }
+
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+
+            fn link(idx: usize) -> $link {
+                // Indexes are smaller than the number of nodes,
+                // which fits in the index type, so this is never 0
+                <$link>::new(idx as $index + 1).unwrap()
+            }
+
+            fn unlink(link: $link) -> usize {
+                (link.get() - 1) as usize
+            }
+        }
+    };
+}
+
+node_index!(u32, NonZeroU32);
+node_index!(u64, NonZeroU64);
+node_index!(usize, NonZeroUsize);
+
+/// The error we return when an array has more values than
+/// the nodes of a tree can address with the chosen index type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexWidthError {
+    /// The number of values in the array
+    pub len: usize,
+
+    /// The largest number of values that the index type can address
+    pub max_len: usize,
+}
+
+impl fmt::Display for IndexWidthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "an array of {} values is too large for a tree of at most {} nodes",
+            self.len, self.max_len
+        )
+    }
+}
+
+impl std::error::Error for IndexWidthError {}
+
+/// Makes sure that indexes of type `I` can address `len` nodes
+pub(crate) fn check_width<I: NodeIndex>(len: usize) -> Result<(), IndexWidthError> {
+    let max_len = I::max_len();
+    if len > max_len {
+        return Err(IndexWidthError { len, max_len });
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+//! Traversing a Cartesian Tree
+//!
+//! Iterators that lazily walk a [`CartesianTree`](crate::CartesianTree) in the usual orders,
+//! yielding the index of each node in the underlying array along with its
+//! value. Since a sorted array produces a tree shaped like a path, a tree
+//! can be as deep as the array is long. We therefore never recurse; each
+//! iterator keeps an explicit stack (or queue) of the nodes it still has to
+//! visit instead.
+
+use crate::index::NodeIndex;
+use crate::tree::{CartesianNodeIdx, Links};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Visits the left subtree, then the node, then the right subtree.
+/// This yields the underlying array in order.
+pub struct InOrder<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+
+    /// The nodes whose left subtrees we are currently visiting
+    stack: Vec<usize>,
+}
+
+/// Visits the node, then its left subtree, then its right subtree.
+pub struct PreOrder<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+
+    /// The roots of the subtrees we have yet to visit
+    stack: Vec<usize>,
+}
+
+/// Visits the left subtree, then the right subtree, then the node.
+pub struct PostOrder<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+
+    /// The nodes we have yet to yield, along with whether
+    /// we have already pushed their children onto the stack
+    stack: Vec<(usize, bool)>,
+}
+
+/// Visits the nodes one level at a time, from the root
+/// down, and from left to right within a level.
+pub struct LevelOrder<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+
+    /// The nodes we have discovered but not yet visited
+    queue: VecDeque<usize>,
+}
+
+/// Visits the nodes in the order of their indexes, yielding
+/// the range in which each of them is the minimum.
+pub struct MinSpans<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+    indexes: Range<usize>,
+}
+
+/// The maximal range of the underlying array in which a value is the minimum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinSpan<'t, T> {
+    /// The index of the value in the underlying array
+    pub index: usize,
+    pub value: &'t T,
+
+    /// The range in which the value is the minimum, and its length
+    pub span: Range<usize>,
+    pub len: usize,
+}
+
+impl<'t, T, I: NodeIndex> InOrder<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>) -> Self {
+        let mut iter = InOrder {
+            values,
+            links,
+            stack: Vec::new(),
+        };
+        iter.push_left_spine(&links.root_idx);
+        iter
+    }
+
+    /// Pushes the given node and all of its left descendants
+    fn push_left_spine(&mut self, mut cur_idx: &'t Option<CartesianNodeIdx<I>>) {
+        while let Some(idx) = cur_idx {
+            self.stack.push(idx.get());
+            cur_idx = &self.links.nodes[idx.get()].left_child_idx;
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> Iterator for InOrder<'t, T, I> {
+    type Item = (usize, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.stack.pop()?;
+        self.push_left_spine(&self.links.nodes[idx].right_child_idx);
+        Some((idx, &self.values[idx]))
+    }
+}
+
+impl<'t, T, I: NodeIndex> PreOrder<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>) -> Self {
+        let stack = links.root_idx.iter().map(|root| root.get()).collect();
+        PreOrder {
+            values,
+            links,
+            stack,
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> Iterator for PreOrder<'t, T, I> {
+    type Item = (usize, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.stack.pop()?;
+        let node = &self.links.nodes[idx];
+        // The left subtree goes on top so that we visit it first
+        self.stack
+            .extend(node.right_child_idx.iter().map(|r| r.get()));
+        self.stack
+            .extend(node.left_child_idx.iter().map(|l| l.get()));
+        Some((idx, &self.values[idx]))
+    }
+}
+
+impl<'t, T, I: NodeIndex> PostOrder<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>) -> Self {
+        let stack = links
+            .root_idx
+            .iter()
+            .map(|root| (root.get(), false))
+            .collect();
+        PostOrder {
+            values,
+            links,
+            stack,
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> Iterator for PostOrder<'t, T, I> {
+    type Item = (usize, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (idx, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some((idx, &self.values[idx]));
+            }
+            // We come back to this node once both of its subtrees are done
+            let node = &self.links.nodes[idx];
+            self.stack.push((idx, true));
+            self.stack
+                .extend(node.right_child_idx.iter().map(|r| (r.get(), false)));
+            self.stack
+                .extend(node.left_child_idx.iter().map(|l| (l.get(), false)));
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> LevelOrder<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>) -> Self {
+        let queue = links.root_idx.iter().map(|root| root.get()).collect();
+        LevelOrder {
+            values,
+            links,
+            queue,
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> Iterator for LevelOrder<'t, T, I> {
+    type Item = (usize, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.queue.pop_front()?;
+        let node = &self.links.nodes[idx];
+        self.queue
+            .extend(node.left_child_idx.iter().map(|l| l.get()));
+        self.queue
+            .extend(node.right_child_idx.iter().map(|r| r.get()));
+        Some((idx, &self.values[idx]))
+    }
+}
+
+impl<'t, T, I: NodeIndex> MinSpans<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>) -> Self {
+        let indexes = 0..values.len();
+        MinSpans {
+            values,
+            links,
+            indexes,
+        }
+    }
+}
+
+impl<'t, T, I: NodeIndex> Iterator for MinSpans<'t, T, I> {
+    type Item = MinSpan<'t, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.indexes.next()?;
+        let span = self.links.nodes[index].span();
+        Some(MinSpan {
+            index,
+            value: &self.values[index],
+            len: span.len(),
+            span,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indexes.size_hint()
+    }
+}
+
+impl<'t, T, I: NodeIndex> ExactSizeIterator for MinSpans<'t, T, I> {}
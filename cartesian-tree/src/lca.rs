@@ -0,0 +1,351 @@
+//! Lowest Common Ancestor
+//!
+//! The lowest common ancestor of two nodes in a cartesian tree is the
+//! minimum of the range of the underlying array that they delimit. To
+//! answer LCA queries quickly, we reduce them back to RMQ: we record the
+//! Euler tour of the tree along with the depth of each visited node. The
+//! LCA of `u` and `v` is the shallowest node visited between the first
+//! visits to `u` and `v`.
+//!
+//! Adjacent depths in the Euler tour differ by exactly one. This lets us
+//! answer the RMQ over the depths with the `±1 RMQ` structure: we split the
+//! depths into blocks of size `b = lg(n) / 2` and build a sparse table over
+//! the block minima. Each block is fully described, as far as RMQ is
+//! concerned, by the sequence of its `b - 1` up/down steps. Blocks with the
+//! same steps share an in-block lookup table, and there are only
+//! `2^(b - 1) = O(sqrt(n))` possible step sequences. This gives us
+//! `O(n)` preprocessing and `O(1)` queries.
+//!
+//! The tour, the depths and the tables of locations hold indexes of the same
+//! type as the nodes of the tree, so a tree with `u32` indexes also gets an
+//! LCA structure half the size of the default one.
+
+use crate::index::NodeIndex;
+use crate::sparse_table::floor_lg;
+use crate::tree::{CartesianNodeIdx, CartesianTreeNode};
+
+/// Answers lowest common ancestor queries over the nodes of a
+/// cartesian tree using an Euler tour and a `±1 RMQ` structure
+/// built over the depths of the nodes in the tour.
+#[derive(Debug)]
+pub(crate) struct LowestCommonAncestor<I: NodeIndex = usize> {
+    /// The nodes of the tree in the order in which an Euler tour visits them
+    euler_tour: Vec<I>,
+
+    /// For each node, the location of its first appearance in the Euler tour
+    first_visit: Vec<I>,
+
+    /// Answers range min queries over the depths of the nodes in the tour
+    depths: PlusMinusOneRmq<I>,
+}
+
+impl<I: NodeIndex> LowestCommonAncestor<I> {
+    /// Builds the LCA structure for the tree rooted at `root_idx`. With the
+    /// `rayon` feature, the tours of large trees are written in parallel.
+    pub(crate) fn build(
+        nodes: &[CartesianTreeNode<I>],
+        root_idx: Option<&CartesianNodeIdx<I>>,
+    ) -> Self {
+        // The tour visits every node once on the way down,
+        // and once more on the way back up from every child
+        let len = nodes.len();
+        let tour_len = (2 * len).saturating_sub(1);
+        let mut euler_tour = vec![I::default(); tour_len];
+        let mut depths = vec![I::default(); tour_len];
+        let mut first_visit = vec![I::default(); len];
+        if let Some(root) = root_idx {
+            let tour = TourPart {
+                nodes: &mut euler_tour,
+                depths: &mut depths,
+                first_visit: &mut first_visit,
+                start: 0,
+                first_node: 0,
+            };
+            // Small trees are not worth the cost of spawning tasks
+            #[cfg(feature = "rayon")]
+            if len > crate::parallel::SEQUENTIAL_CUTOFF {
+                crate::parallel::walk_in_parallel(nodes, root.get(), tour);
+            } else {
+                Self::walk(nodes, root.get(), 0, tour);
+            }
+            #[cfg(not(feature = "rayon"))]
+            Self::walk(nodes, root.get(), 0, tour);
+        }
+        LowestCommonAncestor {
+            euler_tour,
+            first_visit,
+            depths: PlusMinusOneRmq::from(depths),
+        }
+    }
+
+    /// Writes the Euler tour of the subtree rooted at `root`, which is at
+    /// depth `depth` in the whole tree. The tour is generated with an
+    /// explicit stack so that path shaped trees do not overflow the call
+    /// stack.
+    pub(crate) fn walk(
+        nodes: &[CartesianTreeNode<I>],
+        root: usize,
+        depth: usize,
+        mut tour: TourPart<'_, I>,
+    ) {
+        // Each entry holds a node and how many of its children we have
+        // already descended into.
+        let mut stack = vec![(root, 0u8)];
+        let mut position = 0;
+        while let Some(top) = stack.last_mut() {
+            let (node_idx, visited) = *top;
+            top.1 += 1;
+            let node = &nodes[node_idx];
+            match visited {
+                0 => {
+                    tour.first_visit(position, node_idx);
+                    tour.visit(position, node_idx, depth + stack.len() - 1);
+                    position += 1;
+                    if let Some(left) = &node.left_child_idx {
+                        stack.push((left.get(), 0));
+                    }
+                }
+                1 => {
+                    if let Some(right) = &node.right_child_idx {
+                        stack.push((right.get(), 0));
+                    }
+                }
+                _ => {
+                    // We are done with this subtree. Going back up to
+                    // the parent is part of the tour.
+                    stack.pop();
+                    if let Some(&(parent_idx, _)) = stack.last() {
+                        tour.visit(position, parent_idx, depth + stack.len() - 1);
+                        position += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds the lowest common ancestor of the nodes at the two indexes
+    pub(crate) fn query(&self, u: usize, v: usize) -> usize {
+        let (mut lo, mut hi) = (
+            self.first_visit[u].to_usize(),
+            self.first_visit[v].to_usize(),
+        );
+        if lo > hi {
+            std::mem::swap(&mut lo, &mut hi);
+        }
+        self.euler_tour[self.depths.query(lo, hi)].to_usize()
+    }
+
+    /// The depth of the node at the given index. The root has depth 0
+    pub(crate) fn depth(&self, u: usize) -> usize {
+        self.depths.values[self.first_visit[u].to_usize()].to_usize()
+    }
+}
+
+/// The part of the Euler tour that belongs to a subtree, which spans the
+/// nodes in `[first_node, first_node + first_visit.len())`. Its tour has
+/// two entries per node but one, and starts at position `start` of the
+/// whole tour.
+pub(crate) struct TourPart<'t, I> {
+    nodes: &'t mut [I],
+    depths: &'t mut [I],
+    first_visit: &'t mut [I],
+    start: usize,
+    first_node: usize,
+}
+
+impl<'t, I: NodeIndex> TourPart<'t, I> {
+    /// The number of nodes in the subtree
+    #[cfg(feature = "rayon")]
+    pub(crate) fn len(&self) -> usize {
+        self.first_visit.len()
+    }
+
+    fn visit(&mut self, position: usize, node_idx: usize, depth: usize) {
+        self.nodes[position] = I::from_usize(node_idx);
+        self.depths[position] = I::from_usize(depth);
+    }
+
+    fn first_visit(&mut self, position: usize, node_idx: usize) {
+        self.first_visit[node_idx - self.first_node] = I::from_usize(self.start + position);
+    }
+
+    /// Records the visits to `root`, the root of the subtree, which is at
+    /// depth `depth`, and splits the rest of the tour between its left and
+    /// right subtrees. The tour first visits the root, then takes `2s` steps
+    /// for a child with `s` nodes, the last of which comes back to the root.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn split(mut self, root: usize, depth: usize) -> (Self, Self) {
+        self.first_visit(0, root);
+        self.visit(0, root, depth);
+        let left_len = root - self.first_node;
+        let right_len = self.len() - left_len - 1;
+        let right_start = 1 + 2 * left_len;
+        if left_len > 0 {
+            self.visit(right_start - 1, root, depth);
+        }
+        if right_len > 0 {
+            self.visit(self.nodes.len() - 1, root, depth);
+        }
+
+        let TourPart {
+            nodes,
+            depths,
+            first_visit,
+            start,
+            first_node,
+        } = self;
+        let (nodes, depths) = (&mut nodes[1..], &mut depths[1..]);
+        let (left_nodes, right_nodes) = nodes.split_at_mut(2 * left_len);
+        let (left_depths, right_depths) = depths.split_at_mut(2 * left_len);
+        let (left_first, right_first) = first_visit.split_at_mut(left_len);
+        let right_tour_len = (2 * right_len).saturating_sub(1);
+        let left = TourPart {
+            nodes: &mut left_nodes[..(2 * left_len).saturating_sub(1)],
+            depths: &mut left_depths[..(2 * left_len).saturating_sub(1)],
+            first_visit: left_first,
+            start: start + 1,
+            first_node,
+        };
+        let right = TourPart {
+            nodes: &mut right_nodes[..right_tour_len],
+            depths: &mut right_depths[..right_tour_len],
+            first_visit: &mut right_first[1..],
+            start: start + right_start,
+            first_node: root + 1,
+        };
+        (left, right)
+    }
+}
+
+/// A range min query structure specialized for arrays in which
+/// adjacent values differ by exactly one.
+#[derive(Debug)]
+struct PlusMinusOneRmq<I: NodeIndex> {
+    values: Vec<I>,
+
+    /// The number of items in every block but, possibly, the last one
+    block_size: usize,
+
+    /// `sparse_table[k][i]` is the location of the minimum value in the
+    /// `2^k` blocks starting at block `i`
+    sparse_table: Vec<Vec<I>>,
+
+    /// The up/down signature of each block
+    block_signatures: Vec<I>,
+
+    /// In-block lookup tables indexed by block signature. Entry
+    /// `i * block_size + j` holds the offset of the minimum value in the
+    /// inclusive range `[i, j]` of the block. Tables for signatures that do
+    /// not occur are left empty.
+    in_block_tables: Vec<Vec<u8>>,
+}
+
+impl<I: NodeIndex> From<Vec<I>> for PlusMinusOneRmq<I> {
+    fn from(values: Vec<I>) -> Self {
+        let len = values.len();
+        let lg_len = if len < 2 { 0 } else { floor_lg(len) };
+        let block_size = (lg_len / 2).max(1);
+
+        // A signature starts with a sentinel 1 bit followed by one bit per
+        // step, so that blocks of different lengths get different signatures.
+        let mut in_block_tables = vec![Vec::new(); 1 << block_size];
+        let mut block_signatures = Vec::with_capacity(len / block_size + 1);
+        let mut block_mins = Vec::with_capacity(len / block_size + 1);
+        for (block_idx, block) in values.chunks(block_size).enumerate() {
+            let signature = block
+                .windows(2)
+                .fold(1, |sig, step| (sig << 1) | (step[1] > step[0]) as usize);
+            if in_block_tables[signature].is_empty() {
+                in_block_tables[signature] = Self::build_in_block_table(block, block_size);
+            }
+            let min_offset = in_block_tables[signature][block.len() - 1] as usize;
+            block_mins.push(I::from_usize(block_idx * block_size + min_offset));
+            block_signatures.push(I::from_usize(signature));
+        }
+        let sparse_table = Self::build_sparse_table(&values, block_mins);
+        PlusMinusOneRmq {
+            values,
+            block_size,
+            sparse_table,
+            block_signatures,
+            in_block_tables,
+        }
+    }
+}
+
+impl<I: NodeIndex> PlusMinusOneRmq<I> {
+    /// Returns the location of the minimum value in the inclusive range `[first, last]`
+    fn query(&self, first: usize, last: usize) -> usize {
+        let (first_block, last_block) = (first / self.block_size, last / self.block_size);
+        if first_block == last_block {
+            return self.in_block_query(first_block, first, last);
+        }
+        let block_end = (first_block + 1) * self.block_size - 1;
+        let mut min_idx = self.in_block_query(first_block, first, block_end);
+        if first_block + 1 < last_block {
+            let middle = self.sparse_query(first_block + 1, last_block - 1);
+            min_idx = self.min_of(min_idx, middle);
+        }
+        let block_start = last_block * self.block_size;
+        let last_min = self.in_block_query(last_block, block_start, last);
+        self.min_of(min_idx, last_min)
+    }
+
+    fn in_block_query(&self, block_idx: usize, first: usize, last: usize) -> usize {
+        let block_start = block_idx * self.block_size;
+        let table = &self.in_block_tables[self.block_signatures[block_idx].to_usize()];
+        let (i, j) = (first - block_start, last - block_start);
+        block_start + table[i * self.block_size + j] as usize
+    }
+
+    fn sparse_query(&self, first: usize, last: usize) -> usize {
+        let k = floor_lg(last - first + 1);
+        let left = self.sparse_table[k][first].to_usize();
+        let right = self.sparse_table[k][last + 1 - (1 << k)].to_usize();
+        self.min_of(left, right)
+    }
+
+    fn min_of(&self, left: usize, right: usize) -> usize {
+        if self.values[right] < self.values[left] {
+            right
+        } else {
+            left
+        }
+    }
+
+    fn build_in_block_table(block: &[I], block_size: usize) -> Vec<u8> {
+        let mut table = vec![0; block_size * block_size];
+        for i in 0..block.len() {
+            let mut min_offset = i;
+            for j in i..block.len() {
+                if block[j] < block[min_offset] {
+                    min_offset = j;
+                }
+                table[i * block_size + j] = min_offset as u8;
+            }
+        }
+        table
+    }
+
+    fn build_sparse_table(values: &[I], block_mins: Vec<I>) -> Vec<Vec<I>> {
+        let num_blocks = block_mins.len();
+        let mut sparse_table = vec![block_mins];
+        let mut window = 1;
+        while 2 * window <= num_blocks {
+            let prev = sparse_table.last().unwrap();
+            let next = (0..=num_blocks - 2 * window)
+                .map(|i| {
+                    let (l, r) = (prev[i], prev[i + window]);
+                    if values[r.to_usize()] < values[l.to_usize()] {
+                        r
+                    } else {
+                        l
+                    }
+                })
+                .collect();
+            sparse_table.push(next);
+            window *= 2;
+        }
+        sparse_table
+    }
+}
@@ -1,7 +1,7 @@
 //! # Cartesian Tree
 //!
 //! Convert any array into a Cartesian Tree in Linear Time.
-//! 
+//!
 //! ## Usage
 //! ```rust
 //! let v = [93, 84, 33, 64, 62, 83, 63];
@@ -9,6 +9,9 @@
 //! for (&l, &r) in tree.in_order_traversal().into_iter().zip(v.iter()) {
 //!   assert_eq!(l, r);
 //! }
+//!
+//! // The tree answers range min queries over the array
+//! assert_eq!(tree.range_min(3, 6), (4, &62));
 //! ```
 //!
 //! ## Background
@@ -22,7 +25,7 @@
 //! * An in-order traversal must yield the array elements in their
 //! * The tree should be a min heap. That is, the smallest element should be at the root.
 //! * When doing an in-order traversal, the right child is retrieved after both the parent and the left child —
-//! consequently, the right-most node will be the last node retrieved.
+//!   consequently, the right-most node will be the last node retrieved.
 //!
 //! ## Wait, but Why?
 //!
@@ -34,6 +37,18 @@
 //!
 //! Traversing the right spine of tree from the right-most node can be done efficiently by keeping nodes on the right spine in a stack. That way, the rightmost node is always at the top of the stack.
 //!
+//! The nodes of a tree refer to each other by index. A [`CartesianTree`] with `u32` indexes, built with [`CartesianTree::try_from_slice_with`], holds up to `2^31` values with a node arena and an LCA structure half the size of the default ones.
+//!
+//! Besides the tree itself, the crate has three range min query indexes over an array, which take `[i, j)` ranges:
+//!
+//! * [`SparseTableRmq`] takes `O(n lg n)` time and words to build, and answers queries in constant time.
+//! * [`FischerHeunRmq`] takes `O(n)` time and words to build, and answers queries in constant time.
+//! * [`SuccinctRmq`] takes `2n + O(n / lg n)` bits, and answers queries without the array, but in `O(lg n)` time. It follows Fischer's `2n` bit structure, without the directories that bring its queries down to constant time.
+//!
+//! With the optional `rayon` feature, `CartesianTree::par_from`, and `CartesianTree::try_par_from_slice_with` for compact indexes, build the trees of the two halves of a large array in parallel, and stitch them together along the right spine of one and the left spine of the other.
+//!
+//! With the optional `serde` feature, `CartesianTree::shape` takes out the shape of a tree, so that it can be saved apart from the values. `CartesianTree::from_shape` attaches a saved shape back to its array, after checking that the two fit together. A [`SuccinctRmq`] saves its parentheses, and checks that they are balanced when it is loaded. The other range min indexes cannot be saved: the LCA structure of a loaded tree is built again on its first range min query, and a [`FischerHeunRmq`] or [`SparseTableRmq`] is built again from its array.
+//!
 //! ## Cartesian Tree Isomorphisms
 //!
 //! When do two cartesian trees for two different arrays, `A` and `B`,  have the same shape? How can we tell this efficiently?
@@ -41,14 +56,75 @@
 //! Put simply, if two arrays have the same cartesian tree shape, then the minimal values, in **any** range, in both arrays occur at the same index. This means that, the sequence of `Push` and `Pop` operations when constructing the cartesian trees for the two arrays are exactly the same. Therefore, to know if two arrays are isomorphic, we could simply compare the operations needed to construct each tree.
 //!
 //! As an aside, when we are only interested in whether two arrays have isomorphic trees, we don't even need to construct the tree. We can instead create a bit-string from the sequence of `Push` and `Pop` operations. The number formed by this bit-string is called the `cartesian tree number`. Therefore, with this scheme, ***two arrays have isomorphic trees if they have the same cartesian tree number.***
+//!
+//! [`same_shape`] compares two arrays this way, stopping at the first action on which they differ, while a [`SignatureBuilder`] computes the cartesian tree number of a stream of values.
 
+pub mod builder;
+pub mod fischer_heun;
+pub mod histogram;
+pub mod implicit_treap;
+pub mod index;
+pub mod iter;
+mod lca;
+pub mod matcher;
+pub mod nearest;
+pub mod node;
+pub mod number;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "serde")]
+pub mod persist;
+pub mod shapes;
+pub mod sparse_table;
+pub mod succinct;
+pub mod treap;
+mod treap_node;
 pub mod tree;
-pub use tree::CartesianTree;
+pub use builder::CartesianTreeBuilder;
+pub use fischer_heun::FischerHeunRmq;
+pub use implicit_treap::ImplicitTreap;
+pub use index::{IndexWidthError, NodeIndex};
+pub use matcher::CartesianTreeMatcher;
+pub use node::NodeRef;
+pub use number::{same_shape, CartesianTreeNumber, SignatureBuilder};
+#[cfg(feature = "serde")]
+pub use persist::{ShapeError, TreeShape};
+pub use shapes::Shapes;
+pub use sparse_table::SparseTableRmq;
+pub use succinct::SuccinctRmq;
+pub use treap::Treap;
+pub use tree::{CartesianTree, OwnedCartesianTree, TieBreak};
 
 #[cfg(test)]
 mod test_cartesian_tree {
-    use super::tree;
+    use super::{
+        builder, fischer_heun, histogram, implicit_treap, matcher, nearest, number, shapes,
+        sparse_table, succinct, treap, tree,
+    };
     use pretty_assertions::assert_eq;
+
+    /// Generates a deterministic pseudo-random array with many duplicates
+    fn pseudo_random_array(len: usize, modulus: u64) -> Vec<u64> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state % modulus
+            })
+            .collect()
+    }
+
+    /// The index of the rightmost minimum in `v[i..j]`
+    fn naive_rmq<T: Ord>(v: &[T], i: usize, j: usize) -> usize {
+        (i..j).fold(i, |min, k| if v[k] <= v[min] { k } else { min })
+    }
+
+    /// The index of the leftmost minimum in `v[i..j]`
+    fn naive_leftmost_rmq<T: Ord>(v: &[T], i: usize, j: usize) -> usize {
+        (i..j).fold(i, |min, k| if v[k] < v[min] { k } else { min })
+    }
     #[test]
     fn test_cartesian_tree() {
         let v = [93, 84, 33, 64, 62, 83, 63];
@@ -58,4 +134,1109 @@ mod test_cartesian_tree {
             assert_eq!(l, r);
         }
     }
+
+    #[test]
+    fn test_range_min() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        for i in 0..v.len() {
+            for j in i..v.len() {
+                let (min_idx, &min) = tree.range_min(i, j);
+                assert_eq!(min, *v[i..=j].iter().min().unwrap());
+                assert_eq!(v[min_idx], min);
+                assert_eq!(tree.range_min(j, i).0, min_idx);
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_min_on_sorted_input() {
+        let v: Vec<_> = (0..10_000).collect();
+        let tree: tree::CartesianTree<'_, _> = v.as_slice().into();
+        assert_eq!(tree.range_min(17, 9_999), (17, &17));
+        assert_eq!(tree.range_min(9_999, 9_999), (9_999, &9_999));
+    }
+
+    #[test]
+    fn test_fischer_heun_rmq() {
+        for &len in &[1, 2, 7, 64, 300] {
+            let v = pseudo_random_array(len, 20);
+            let rmq: fischer_heun::FischerHeunRmq<'_, _> = v.as_slice().into();
+            for i in 0..len {
+                for j in (i + 1)..=len {
+                    assert_eq!(rmq.query(i..j), naive_rmq(&v, i, j));
+                }
+            }
+        }
+
+        // Large enough for blocks of 4 items
+        let v = pseudo_random_array(70_000, 1_000);
+        let rmq: fischer_heun::FischerHeunRmq<'_, _> = v.as_slice().into();
+        for i in (0..v.len()).step_by(997) {
+            for j in (i + 1..=v.len()).step_by(1_009) {
+                assert_eq!(rmq.query(i..j), naive_rmq(&v, i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_table_rmq() {
+        let v = pseudo_random_array(300, 20);
+        let sparse: sparse_table::SparseTableRmq<'_, _> = v.as_slice().into();
+        let fischer_heun: fischer_heun::FischerHeunRmq<'_, _> = v.as_slice().into();
+        for i in 0..v.len() {
+            for j in (i + 1)..=v.len() {
+                assert_eq!(sparse.query(i..j), naive_rmq(&v, i, j));
+                assert_eq!(sparse.query(i..j), fischer_heun.query(i..j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lca() {
+        // The tree looks like this:
+        //          12
+        //        /    \
+        //      33      47
+        //     /  \    /  \
+        //   84   62  47   90
+        //   /   /  \
+        //  93  64   63
+        //           /
+        //          83
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        assert_eq!(tree.lca(0, 3), 2);
+        assert_eq!(tree.lca(3, 5), 4);
+        assert_eq!(tree.lca(5, 6), 6);
+        assert_eq!(tree.lca(0, 10), 7);
+        assert_eq!(tree.lca(8, 10), 9);
+        assert_eq!(tree.lca(4, 4), 4);
+        let depths: Vec<_> = (0..v.len()).map(|i| tree.depth(i)).collect();
+        assert_eq!(depths, vec![3, 2, 1, 3, 2, 4, 3, 0, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_lca_on_large_input() {
+        let v = pseudo_random_array(50_000, 1_000);
+        let tree: tree::CartesianTree<'_, _> = v.as_slice().into();
+        for i in (0..v.len()).step_by(499) {
+            for j in (i..v.len()).step_by(1_201) {
+                assert_eq!(tree.lca(i, j), naive_rmq(&v, i, j + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_orderings() {
+        let v = [93, 84, 33, 64, 62, 83, 63];
+        let max_tree = tree::CartesianTree::max_from(&v);
+        assert_eq!(max_tree.range_min(0, 6), (0, &93));
+        assert_eq!(max_tree.range_min(2, 6), (5, &83));
+        assert_eq!(max_tree.lca(3, 4), 3);
+
+        let reversed = tree::CartesianTree::from_slice_by(&v, |a, b| b.cmp(a));
+        for i in 0..v.len() {
+            for j in i..v.len() {
+                assert_eq!(reversed.range_min(i, j), max_tree.range_min(i, j));
+            }
+        }
+
+        // Types that are not `Ord` can still be organized by one of their fields
+        #[derive(Debug, PartialEq)]
+        struct Reading {
+            at: f64,
+            level: u32,
+        }
+        let readings: Vec<_> = [(0.5, 7), (1.5, 3), (2.5, 9), (3.5, 4)]
+            .iter()
+            .map(|&(at, level)| Reading { at, level })
+            .collect();
+        let tree = tree::CartesianTree::from_slice_by_key(&readings, |r| r.level);
+        let (idx, min) = tree.range_min(2, 3);
+        assert_eq!((idx, min.at), (3, 3.5));
+        assert_eq!(tree.range_min(0, 3).0, 1);
+    }
+
+    #[test]
+    fn test_tie_breaking() {
+        use super::TieBreak;
+        let all_equal = vec![7; 40];
+        let few_values = pseudo_random_array(300, 3);
+        for v in [all_equal, few_values] {
+            for tie_break in [TieBreak::Leftmost, TieBreak::Rightmost] {
+                let naive = match tie_break {
+                    TieBreak::Leftmost => naive_leftmost_rmq::<u64>,
+                    TieBreak::Rightmost => naive_rmq::<u64>,
+                };
+                let tree = tree::CartesianTree::with_tie_break(&v, tie_break);
+                let sparse = sparse_table::SparseTableRmq::with_tie_break(&v, tie_break);
+                let fischer_heun = fischer_heun::FischerHeunRmq::with_tie_break(&v, tie_break);
+                for i in 0..v.len() {
+                    for j in (i + 1)..=v.len() {
+                        let expected = naive(&v, i, j);
+                        assert_eq!(tree.range_min(i, j - 1).0, expected);
+                        assert_eq!(sparse.query(i..j), expected);
+                        assert_eq!(fischer_heun.query(i..j), expected);
+                    }
+                }
+            }
+        }
+
+        // Equal values produce mirrored trees: a left path for the
+        // rightmost policy, and a right path for the leftmost one.
+        let v = [5, 5, 5, 5];
+        let rightmost = tree::CartesianTree::with_tie_break(&v, TieBreak::Rightmost);
+        let leftmost = tree::CartesianTree::with_tie_break(&v, TieBreak::Leftmost);
+        assert_eq!(rightmost.cartesian_tree_number(), 0b1010101);
+        assert_eq!(leftmost.cartesian_tree_number(), 0b1111);
+        assert_eq!(rightmost.depth(0), 3);
+        assert_eq!(leftmost.depth(0), 0);
+    }
+
+    #[test]
+    fn test_owned_cartesian_tree() {
+        let tree: tree::OwnedCartesianTree<_> = {
+            let v = vec![93, 84, 33, 64, 62, 83, 63];
+            let borrowed: tree::CartesianTree<'_, _> = v.as_slice().into();
+            borrowed.into_owned()
+        };
+        let handle = std::thread::spawn(move || {
+            assert_eq!(tree.values(), &[93, 84, 33, 64, 62, 83, 63]);
+            assert_eq!(tree.range_min(3, 6), (4, &62));
+            tree
+        });
+        let tree = handle.join().unwrap();
+        assert_eq!(tree.lca(0, 1), 1);
+
+        let strings = vec!["pear".to_string(), "apple".to_string(), "fig".to_string()];
+        let tree =
+            tree::OwnedCartesianTree::from_vec_with(strings, super::TieBreak::Leftmost, |a, b| {
+                a.len().cmp(&b.len())
+            });
+        assert_eq!(tree.range_min(0, 2), (2, &"fig".to_string()));
+        assert_eq!(tree.range_min(0, 1), (0, &"pear".to_string()));
+
+        // An owned tree may hold values that borrow from elsewhere
+        let text = String::from("kiwi fig banana date");
+        let words: Vec<&str> = text.split(' ').collect();
+        let tree = tree::OwnedCartesianTree::from(words);
+        assert_eq!(tree.range_min(0, 3), (2, &"banana"));
+        let tree = tree.concat(vec!["apple"].into());
+        assert_eq!(tree.root().unwrap().value(), &"apple");
+    }
+
+    #[test]
+    fn test_node_navigation() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        let root = tree.root().unwrap();
+        assert_eq!((root.array_index(), root.value()), (7, &12));
+        assert_eq!(root.parent(), None);
+        assert_eq!(root.subtree_range(), 0..11);
+
+        let left = root.left().unwrap();
+        assert_eq!(left.value(), &33);
+        assert_eq!(left.parent(), Some(root));
+        assert_eq!(left.subtree_range(), 0..7);
+        let node = left.right().unwrap().right().unwrap();
+        assert_eq!((node.array_index(), node.depth()), (6, 3));
+        assert_eq!(node.subtree_range(), 5..7);
+        assert_eq!(tree.node(5).unwrap().parent(), Some(node));
+        assert!(tree.node(11).is_none());
+
+        // A divide and conquer pass over the min positions: the sum, over
+        // all subtrees, of the subtree minimum times the subtree size.
+        let mut total = 0;
+        let mut pending = vec![root];
+        while let Some(node) = pending.pop() {
+            total += node.value() * node.subtree_range().len();
+            pending.extend(node.left());
+            pending.extend(node.right());
+        }
+        let expected: usize = (0..v.len())
+            .map(|i| {
+                let range = tree.node(i).unwrap().subtree_range();
+                v[range.clone()].iter().min().unwrap() * range.len()
+            })
+            .sum();
+        assert_eq!(total, expected);
+        assert!(tree::CartesianTree::<u32>::from(&[][..]).root().is_none());
+    }
+
+    #[test]
+    fn test_min_spans() {
+        use super::TieBreak;
+        let v = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5];
+        for tie_break in [TieBreak::Leftmost, TieBreak::Rightmost] {
+            let tree = tree::CartesianTree::with_tie_break(&v, tie_break);
+            for i in 0..v.len() {
+                // The span ends right before the first smaller value, and
+                // stops at equal values on the side that wins ties.
+                let stops_left = |k: usize| match tie_break {
+                    TieBreak::Leftmost => v[k] <= v[i],
+                    TieBreak::Rightmost => v[k] < v[i],
+                };
+                let stops_right = |k: usize| match tie_break {
+                    TieBreak::Leftmost => v[k] < v[i],
+                    TieBreak::Rightmost => v[k] <= v[i],
+                };
+                let start = (0..i).rev().find(|&k| stops_left(k)).map_or(0, |k| k + 1);
+                let end = (i + 1..v.len())
+                    .find(|&k| stops_right(k))
+                    .unwrap_or(v.len());
+                assert_eq!(tree.min_span(i), start..end);
+                assert_eq!(tree.node(i).unwrap().subtree_range(), start..end);
+            }
+        }
+    }
+
+    #[test]
+    fn test_traversals() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        let indexes = |iter: &mut dyn Iterator<Item = (usize, &i32)>| -> Vec<usize> {
+            iter.map(|(idx, &value)| {
+                assert_eq!(value, v[idx]);
+                idx
+            })
+            .collect()
+        };
+        assert_eq!(
+            indexes(&mut tree.iter_in_order()),
+            (0..v.len()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            indexes(&mut tree.iter_pre_order()),
+            vec![7, 2, 1, 0, 4, 3, 6, 5, 9, 8, 10]
+        );
+        assert_eq!(
+            indexes(&mut tree.iter_post_order()),
+            vec![0, 1, 3, 5, 6, 4, 2, 8, 10, 9, 7]
+        );
+        assert_eq!(
+            indexes(&mut tree.iter_level_order()),
+            vec![7, 2, 9, 1, 4, 8, 10, 0, 3, 6, 5]
+        );
+    }
+
+    #[test]
+    fn test_traversals_of_path_shaped_trees() {
+        let sorted: Vec<u32> = (0..1_000_000).collect();
+        let tree: tree::CartesianTree<'_, _> = sorted.as_slice().into();
+        assert!(tree.iter_in_order().map(|(i, _)| i).eq(0..sorted.len()));
+        assert!(tree.iter_pre_order().map(|(i, _)| i).eq(0..sorted.len()));
+        assert!(tree
+            .iter_post_order()
+            .map(|(i, _)| i)
+            .eq((0..sorted.len()).rev()));
+        assert_eq!(tree.in_order_traversal().len(), sorted.len());
+
+        let reversed: Vec<u32> = sorted.into_iter().rev().collect();
+        let tree: tree::CartesianTree<'_, _> = reversed.as_slice().into();
+        assert!(tree
+            .iter_level_order()
+            .map(|(i, _)| i)
+            .eq((0..reversed.len()).rev()));
+        assert!(tree.iter_post_order().map(|(i, _)| i).eq(0..reversed.len()));
+    }
+
+    #[test]
+    fn test_signatures() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        let signature = tree.signature();
+        assert_eq!(signature.to_u64(), Some(tree.cartesian_tree_number()));
+        assert_eq!(
+            signature,
+            number::CartesianTreeNumber::from(0b1101100010110110101u64)
+        );
+        assert_eq!(format!("{:b}", signature), "1101100010110110101");
+
+        // A sorted array only ever pushes, while a reversed one
+        // pops everything but the first item before each push
+        let sorted: Vec<u32> = (0..200).collect();
+        let reversed: Vec<u32> = sorted.iter().rev().cloned().collect();
+        let sorted_tree: tree::CartesianTree<'_, _> = sorted.as_slice().into();
+        let reversed_tree: tree::CartesianTree<'_, _> = reversed.as_slice().into();
+        assert_eq!(sorted_tree.signature().bit_len(), 200);
+        assert_eq!(reversed_tree.signature().bit_len(), 399);
+        assert_eq!(sorted_tree.signature().to_u128(), None);
+        assert_eq!(format!("{:b}", sorted_tree.signature()), "1".repeat(200));
+        assert!(sorted_tree.signature() < reversed_tree.signature());
+        assert!(tree.signature() < sorted_tree.signature());
+
+        // Shifting every value keeps the shape of the tree
+        let v = pseudo_random_array(1000, 50);
+        let shifted: Vec<u64> = v.iter().map(|x| x + 7).collect();
+        let tree: tree::CartesianTree<'_, _> = v.as_slice().into();
+        let shifted_tree: tree::CartesianTree<'_, _> = shifted.as_slice().into();
+        assert_eq!(tree.signature(), shifted_tree.signature());
+
+        // Blocks of 64 or more items are told apart by their shapes
+        let mut seen = std::collections::HashMap::new();
+        for block in v.chunks(100) {
+            let block_tree: tree::CartesianTree<'_, _> = block.into();
+            let signature = block_tree.signature();
+            assert!(signature.bit_len() < 2 * block.len());
+            let first = *seen.entry(signature).or_insert(block);
+            let first_tree: tree::CartesianTree<'_, _> = first.into();
+            assert!(first_tree
+                .iter_pre_order()
+                .map(|(i, _)| i)
+                .eq(block_tree.iter_pre_order().map(|(i, _)| i)));
+        }
+    }
+
+    #[test]
+    fn test_decoding_cartesian_tree_numbers() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        let decoded = tree::OwnedCartesianTree::from_cartesian_tree_number(&tree.signature());
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.values(), &[3, 2, 1, 3, 2, 4, 3, 0, 2, 1, 2]);
+        assert_eq!(decoded.signature(), tree.signature());
+
+        // Decoding every block of a larger array gives us arrays of the
+        // same shape, whichever way we break ties between their values
+        let v = pseudo_random_array(1000, 20);
+        for block in v.chunks(90) {
+            let block_tree: tree::CartesianTree<'_, _> = block.into();
+            let signature = block_tree.signature();
+            let decoded = tree::OwnedCartesianTree::from_cartesian_tree_number(&signature).unwrap();
+            assert_eq!(decoded.values().len(), block.len());
+            assert!(decoded
+                .iter_pre_order()
+                .map(|(i, _)| i)
+                .eq(block_tree.iter_pre_order().map(|(i, _)| i)));
+            for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+                let rebuilt = tree::CartesianTree::with_tie_break(decoded.values(), tie_break);
+                assert_eq!(rebuilt.signature(), signature);
+            }
+        }
+
+        // The empty array has no actions, while a
+        // number whose first action is a pop is invalid
+        let empty = number::CartesianTreeNumber::from(0u64);
+        let empty = tree::OwnedCartesianTree::from_cartesian_tree_number(&empty).unwrap();
+        assert!(empty.root().is_none());
+        for invalid in [0b10u64, 0b1001, 0b10_0011] {
+            let invalid = number::CartesianTreeNumber::from(invalid);
+            assert!(tree::OwnedCartesianTree::from_cartesian_tree_number(&invalid).is_none());
+        }
+    }
+
+    #[test]
+    fn test_shapes() {
+        let catalan = [1, 1, 2, 5, 14, 42, 132, 429, 1430, 4862];
+        for (size, &count) in catalan.iter().enumerate() {
+            let shapes = shapes::Shapes::new(size);
+            assert_eq!(shapes.count(), count);
+            let numbers: Vec<_> = shapes.iter().collect();
+            assert_eq!(numbers.len() as u64, count);
+            for (rank, number) in numbers.iter().enumerate() {
+                assert_eq!(shapes.rank(number), Some(rank as u64));
+                let tree = tree::OwnedCartesianTree::from_cartesian_tree_number(number).unwrap();
+                assert_eq!(tree.values().len(), size);
+            }
+            let distinct: std::collections::HashSet<_> = numbers.into_iter().collect();
+            assert_eq!(distinct.len() as u64, count);
+            assert_eq!(shapes.unrank(count), None);
+        }
+
+        // Blocks have ranks among the shapes of their size
+        let v = pseudo_random_array(1000, 30);
+        let shapes = shapes::Shapes::new(20);
+        for block in v.chunks_exact(20) {
+            let block_tree: tree::CartesianTree<'_, _> = block.into();
+            let rank = shapes.rank(&block_tree.signature()).unwrap();
+            assert!(rank < shapes.count());
+            assert_eq!(shapes.unrank(rank), Some(block_tree.signature()));
+            assert_eq!(shapes::Shapes::new(19).rank(&block_tree.signature()), None);
+        }
+
+        // Trees with 36 nodes are the largest we can rank
+        let shapes = shapes::Shapes::new(shapes::MAX_SHAPE_SIZE);
+        assert_eq!(shapes::catalan(36), 11_959_798_385_860_453_492);
+        let last = shapes.unrank(shapes.count() - 1).unwrap();
+        assert_eq!(shapes.rank(&last), Some(shapes.count() - 1));
+        assert_eq!(
+            shapes.rank(&number::CartesianTreeNumber::from(0b10u64)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_same_shape() {
+        let v = [93, 84, 33, 64, 62, 83, 63];
+        let shifted: Vec<i64> = v.iter().map(|&x| x - 100).collect();
+        assert!(super::same_shape(&v, &shifted));
+        assert!(!super::same_shape(&v, &v[1..]));
+        assert!(!super::same_shape(&v, &[1, 2, 3, 4, 5, 6, 7]));
+        assert!(super::same_shape::<u8, u8>(&[], &[]));
+
+        // Windows have the same shape exactly when they have the same signature
+        let text = pseudo_random_array(2000, 4);
+        let pattern = &text[100..106];
+        let pattern_tree: tree::CartesianTree<'_, _> = pattern.into();
+        let mut matches = 0;
+        for window in text.windows(pattern.len()) {
+            let window_tree: tree::CartesianTree<'_, _> = window.into();
+            let same = window_tree.signature() == pattern_tree.signature();
+            assert_eq!(super::same_shape(window, pattern), same);
+            matches += same as usize;
+        }
+        assert!(matches > 1);
+
+        // The streaming builder agrees with the tree, whichever way we break ties
+        for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+            let tree = tree::CartesianTree::with_tie_break(&text, tie_break);
+            let mut builder = number::SignatureBuilder::with_tie_break(tie_break);
+            let pops: usize = text.iter().map(|value| builder.push(value)).sum();
+            let signature = builder.finish();
+            assert_eq!(signature.bit_len(), text.len() + pops);
+            assert_eq!(signature, tree.signature());
+        }
+    }
+
+    #[test]
+    fn test_cartesian_tree_matching() {
+        let text = pseudo_random_array(3000, 5);
+        for pattern_len in [1, 2, 5, 8] {
+            let pattern = &text[500..500 + pattern_len];
+            for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+                let signature_of = |values: &[u64]| {
+                    let mut builder = number::SignatureBuilder::with_tie_break(tie_break);
+                    values.iter().for_each(|value| {
+                        builder.push(value);
+                    });
+                    builder.finish()
+                };
+                let pattern_signature = signature_of(pattern);
+                let expected: Vec<usize> = text
+                    .windows(pattern_len)
+                    .enumerate()
+                    .filter(|(_, window)| signature_of(window) == pattern_signature)
+                    .map(|(start, _)| start)
+                    .collect();
+                let matcher = matcher::CartesianTreeMatcher::with_tie_break(pattern, tie_break);
+                assert_eq!(matcher.find_all(&text), expected);
+                assert!(expected.contains(&500));
+            }
+        }
+
+        // By default, a value pops equal values off the stack just like smaller ones
+        let matcher = matcher::CartesianTreeMatcher::new(&[7, 7, 7]);
+        assert_eq!(matcher.find_all(&[1, 1, 1, 1, 0, 2]), vec![0, 1, 2]);
+        assert_eq!(matcher.find_all(&[1, 1]), Vec::<usize>::new());
+        let matcher = matcher::CartesianTreeMatcher::new::<u8>(&[]);
+        assert_eq!(matcher.find_all(&[1, 2]), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_nearest_values() {
+        let v = pseudo_random_array(500, 10);
+        let naive_left = |beats: fn(u64, u64) -> bool| -> Vec<Option<usize>> {
+            (0..v.len())
+                .map(|i| (0..i).rev().find(|&j| beats(v[j], v[i])))
+                .collect()
+        };
+        let naive_right = |beats: fn(u64, u64) -> bool| -> Vec<Option<usize>> {
+            (0..v.len())
+                .map(|i| (i + 1..v.len()).find(|&j| beats(v[j], v[i])))
+                .collect()
+        };
+        assert_eq!(nearest::nearest_smaller_left(&v), naive_left(|a, b| a < b));
+        assert_eq!(
+            nearest::nearest_smaller_right(&v),
+            naive_right(|a, b| a < b)
+        );
+        assert_eq!(nearest::nearest_greater_left(&v), naive_left(|a, b| a > b));
+        assert_eq!(
+            nearest::nearest_greater_right(&v),
+            naive_right(|a, b| a > b)
+        );
+
+        // The nearest smaller values bound the spans of the tree. Equal
+        // values extend a span towards the ancestor among them.
+        let leftmost = tree::CartesianTree::with_tie_break(&v, tree::TieBreak::Leftmost);
+        let rightmost = tree::CartesianTree::with_tie_break(&v, tree::TieBreak::Rightmost);
+        let left = nearest::nearest_smaller_left(&v);
+        let right = nearest::nearest_smaller_right(&v);
+        for i in 0..v.len() {
+            assert_eq!(leftmost.min_span(i).end, right[i].unwrap_or(v.len()));
+            assert_eq!(rightmost.min_span(i).start, left[i].map_or(0, |j| j + 1));
+        }
+        assert!(nearest::nearest_smaller_left::<u8>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_min_spans_and_largest_rectangles() {
+        let v = [93, 84, 33, 64, 62, 83, 63, 12, 47, 47, 90];
+        let tree: tree::CartesianTree<'_, _> = v.as_ref().into();
+        let spans = tree.min_spans();
+        assert_eq!(spans.len(), v.len());
+        for span in spans {
+            assert_eq!(span.span, tree.min_span(span.index));
+            assert_eq!(span.len, span.span.len());
+            assert_eq!(*span.value, v[span.index]);
+        }
+
+        // Compare against every possible rectangle
+        let naive_largest_area = |heights: &[u64]| {
+            (0..heights.len())
+                .flat_map(|i| (i + 1..=heights.len()).map(move |j| (i, j)))
+                .map(|(i, j)| *heights[i..j].iter().min().unwrap() as u128 * (j - i) as u128)
+                .max()
+        };
+        let heights = pseudo_random_array(300, 40);
+        for window in heights.windows(30).step_by(7) {
+            let rectangle = histogram::largest_rectangle(window).unwrap();
+            assert_eq!(Some(rectangle.area), naive_largest_area(window));
+            let min = *window[rectangle.span.clone()].iter().min().unwrap();
+            assert_eq!(rectangle.height, min);
+            assert_eq!(rectangle.area, min as u128 * rectangle.span.len() as u128);
+        }
+        assert_eq!(histogram::largest_rectangle::<u8>(&[]), None);
+
+        // Sorted heights make a path shaped tree
+        let sorted: Vec<u64> = (1..=1_000_000).collect();
+        let rectangle = histogram::largest_rectangle(&sorted).unwrap();
+        assert_eq!(rectangle.area, 500_000 * 500_001);
+        assert_eq!(rectangle.span, 499_999..1_000_000);
+
+        // Of equal bars, the last one spans the rectangle they share
+        let heights: Vec<usize> = vec![3, 1, 2, 2, 1, 3];
+        let rectangle = histogram::largest_rectangle(&heights).unwrap();
+        assert_eq!(rectangle.span, 0..6);
+        assert_eq!((rectangle.height, rectangle.area), (1, 6));
+        let rectangle = histogram::largest_rectangle(&[2usize, 5, 5, 1]).unwrap();
+        assert_eq!((rectangle.span, rectangle.area), (1..3, 10));
+
+        // The area of the tallest bars does not overflow
+        let rectangle = histogram::largest_rectangle(&[u64::MAX, u64::MAX, 0]).unwrap();
+        assert_eq!(rectangle.span, 0..2);
+        assert_eq!(rectangle.area, u64::MAX as u128 * 2);
+        let rectangle = histogram::largest_rectangle(&[usize::MAX; 3]).unwrap();
+        assert_eq!(rectangle.area, usize::MAX as u128 * 3);
+    }
+
+    #[test]
+    fn test_treap() {
+        use std::collections::BTreeMap;
+
+        let keys = pseudo_random_array(5000, 2000);
+        let mut treap = treap::Treap::with_seed(7);
+        let mut expected = BTreeMap::new();
+        for (i, &key) in keys.iter().enumerate() {
+            if i % 3 == 2 {
+                assert_eq!(treap.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(treap.insert(key, i), expected.insert(key, i));
+            }
+            assert_eq!(treap.get(&key), expected.get(&key));
+        }
+        assert_eq!(treap.len(), expected.len());
+        assert!(treap.iter().eq(expected.iter()));
+        assert_eq!(treap.first_key_value(), expected.iter().next());
+        assert_eq!(treap.last_key_value(), expected.iter().next_back());
+
+        let larger = treap.split_off(&1000);
+        let expected_larger = expected.split_off(&1000);
+        assert!(treap.iter().eq(expected.iter()));
+        assert!(larger.iter().eq(expected_larger.iter()));
+        assert_eq!(larger.len(), expected_larger.len());
+        treap.merge(larger);
+        expected.extend(expected_larger);
+        assert!(treap.iter().eq(expected.iter()));
+
+        // Bulk building takes linear time, even for many keys
+        let treap = treap::Treap::from_sorted((0..1_000_000u32).map(|key| (key, key * 2)));
+        assert_eq!(treap.len(), 1_000_000);
+        assert_eq!(treap.get(&123_456), Some(&246_912));
+        assert_eq!(treap.get(&1_000_000), None);
+        assert!(treap.iter().map(|(&key, _)| key).eq(0..1_000_000));
+    }
+
+    #[test]
+    fn test_implicit_treap() {
+        let values = pseudo_random_array(3000, 100);
+        let mut expected = values[..1000].to_vec();
+        let mut treap: implicit_treap::ImplicitTreap<u64> = expected.clone().into();
+        assert!(treap.iter().eq(expected.iter()));
+
+        // Interleave edits with range min queries, checking against a vector
+        for (step, &value) in values[1000..].iter().enumerate() {
+            let position = (value as usize * 37 + step) % (expected.len() + 1);
+            match step % 4 {
+                0 | 1 => {
+                    treap.insert(position, value);
+                    expected.insert(position, value);
+                }
+                2 if position < expected.len() => {
+                    assert_eq!(treap.remove(position), expected.remove(position));
+                }
+                _ => {
+                    let end = (position + value as usize + 1).min(expected.len());
+                    let start = end.saturating_sub(value as usize % 50 + 1);
+                    let min_idx = naive_rmq(&expected, start, end);
+                    assert_eq!(
+                        treap.range_min(start, end - 1),
+                        (min_idx, &expected[min_idx])
+                    );
+                    assert_eq!(treap.range_min(end - 1, start).0, min_idx);
+                }
+            }
+            assert_eq!(treap.len(), expected.len());
+        }
+        assert!(treap.iter().eq(expected.iter()));
+        assert_eq!(treap.get(17), expected.get(17));
+
+        // Ranges are inclusive, as they are for the static tree
+        let tree = tree::CartesianTree::from(&expected[..]);
+        for (i, j) in [(0, 0), (3, 700), (700, 3), (0, expected.len() - 1)] {
+            assert_eq!(treap.range_min(i, j), tree.range_min(i, j));
+        }
+        assert_eq!(treap.get(expected.len()), None);
+
+        let mut tail = treap.split_off(1000);
+        let expected_tail = expected.split_off(1000);
+        assert!(treap.iter().eq(expected.iter()));
+        assert!(tail.iter().eq(expected_tail.iter()));
+        let min_idx = naive_rmq(&expected_tail, 0, expected_tail.len());
+        assert_eq!(tail.range_min(0, tail.len() - 1).0, min_idx);
+
+        // A copy has its own values and the same shape
+        let copy = tail.clone();
+        assert!(copy.iter().eq(tail.iter()));
+        assert_eq!(
+            copy.range_min(0, copy.len() - 1),
+            tail.range_min(0, tail.len() - 1)
+        );
+
+        // Concatenating in the other order
+        tail.append(treap);
+        let concatenated: Vec<u64> = expected_tail.into_iter().chain(expected).collect();
+        assert!(tail.iter().eq(concatenated.iter()));
+        for start in (0..concatenated.len()).step_by(97) {
+            let end = (start + 300).min(concatenated.len());
+            assert_eq!(
+                tail.range_min(start, end - 1).0,
+                naive_rmq(&concatenated, start, end)
+            );
+        }
+
+        // Values that cannot be cloned
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Owned(u64);
+        let mut owned: implicit_treap::ImplicitTreap<Owned> =
+            values[..500].iter().map(|&value| Owned(value)).collect();
+        let min_idx = naive_rmq(&values[..500], 100, 400);
+        assert_eq!(
+            owned.range_min(100, 399),
+            (min_idx, &Owned(values[min_idx]))
+        );
+        assert_eq!(owned.remove(min_idx), Owned(values[min_idx]));
+        assert_eq!(owned.len(), 499);
+    }
+
+    #[test]
+    fn test_cartesian_tree_builder() {
+        let v = pseudo_random_array(2000, 60);
+        for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+            let naive = match tie_break {
+                tree::TieBreak::Leftmost => naive_leftmost_rmq::<u64>,
+                tree::TieBreak::Rightmost => naive_rmq::<u64>,
+            };
+            let mut builder = builder::CartesianTreeBuilder::with_tie_break(tie_break);
+            assert_eq!(builder.current_root(), None);
+            for (idx, &value) in v[..300].iter().enumerate() {
+                assert_eq!(builder.push(value), idx);
+                let root = naive(&v, 0, idx + 1);
+                assert_eq!(builder.current_root(), Some((root, &v[root])));
+                for i in 0..=idx {
+                    let min_idx = naive(&v, i, idx + 1);
+                    assert_eq!(builder.range_min(i, idx), (min_idx, &v[min_idx]));
+                    assert_eq!(builder.range_min(idx, i).0, min_idx);
+                }
+            }
+            builder.extend(v[300..].iter().cloned());
+            for i in (0..v.len()).step_by(13) {
+                for j in (i..v.len()).step_by(29) {
+                    assert_eq!(builder.range_min(i, j).0, naive(&v, i, j + 1));
+                }
+            }
+
+            // The finished tree is the one we would have built all at once
+            let built = builder.build();
+            let expected = tree::CartesianTree::with_tie_break(&v, tie_break);
+            assert_eq!(built.values(), v.as_slice());
+            assert_eq!(built.signature(), expected.signature());
+            assert!(built.iter_pre_order().eq(expected.iter_pre_order()));
+            assert!(built.min_spans().eq(expected.min_spans()));
+            assert_eq!(built.range_min(17, 1500), expected.range_min(17, 1500));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_parallel_construction() {
+        use super::parallel;
+
+        let random = pseudo_random_array(3000, 40);
+        let sorted: Vec<u64> = (0..3000).collect();
+        let reversed: Vec<u64> = (0..3000).rev().collect();
+        let constant = vec![7u64; 3000];
+        for v in [random, sorted, reversed, constant] {
+            for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+                let expected = tree::CartesianTree::with_tie_break(&v, tie_break);
+                let links = |nodes: &[tree::CartesianTreeNode]| {
+                    nodes
+                        .iter()
+                        .map(|node| {
+                            let idx = |idx: &Option<tree::CartesianNodeIdx>| {
+                                idx.as_ref().map(|idx| idx.get())
+                            };
+                            let span = node.span();
+                            let children = (idx(&node.left_child_idx), idx(&node.right_child_idx));
+                            (children, idx(&node.parent_idx), span)
+                        })
+                        .collect::<Vec<_>>()
+                };
+                for cutoff in [1, 2, 7, 64, 5000] {
+                    let (nodes, root_idx) =
+                        parallel::link_nodes_in_parallel(v.len(), cutoff, &|top, new| {
+                            tie_break.earlier_wins(v[top].cmp(&v[new]))
+                        });
+                    assert_eq!(root_idx, expected.links.root_idx);
+                    assert_eq!(links(&nodes), links(&expected.links.nodes));
+                }
+            }
+        }
+
+        // Large enough to be split with the default cutoff
+        let v = pseudo_random_array(100_000, 1000);
+        for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+            let built = tree::CartesianTree::par_from_slice_with(&v, tie_break, u64::cmp);
+            let expected = tree::CartesianTree::with_tie_break(&v, tie_break);
+            assert_eq!(built.signature(), expected.signature());
+            assert!(built.min_spans().eq(expected.min_spans()));
+            assert_eq!(built.range_min(17, 99_000), expected.range_min(17, 99_000));
+        }
+        let built = tree::CartesianTree::par_from(&v);
+        assert!(built
+            .iter_pre_order()
+            .eq(tree::CartesianTree::from(&v[..]).iter_pre_order()));
+        assert!(tree::CartesianTree::<u64>::par_from(&[]).root().is_none());
+
+        let compact = tree::CartesianTree::<_, u32>::try_par_from_slice_with(
+            &v,
+            tree::TieBreak::default(),
+            u64::cmp,
+        )
+        .unwrap();
+        assert!(compact.min_spans().eq(built.min_spans()));
+        assert_eq!(compact.range_min(17, 99_000), built.range_min(17, 99_000));
+
+        // Path shaped trees keep their whole tour in one task
+        let increasing: Vec<u64> = (0..100_000).collect();
+        let path = tree::CartesianTree::par_from(&increasing);
+        assert_eq!(path.depth(99_999), 99_999);
+        assert_eq!(path.range_min(5, 90_000), (5, &5));
+        let peak: Vec<u64> = (0..100_000).map(|i| i.min(100_000 - i)).collect();
+        for values in [&peak, &v] {
+            let built = tree::CartesianTree::par_from(values);
+            let expected = tree::CartesianTree::from(&values[..]);
+            assert!(built.min_spans().eq(expected.min_spans()));
+            for i in (0..values.len()).step_by(997) {
+                let node = built.node(i).unwrap();
+                let ancestors = std::iter::successors(node.parent(), |node| node.parent());
+                assert_eq!(built.depth(i), ancestors.count());
+                let j = (i + 50_000).min(values.len() - 1);
+                assert_eq!(built.range_min(i, j).0, naive_rmq(values, i, j + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_concat() {
+        let v = pseudo_random_array(500, 30);
+        for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+            let expected = tree::CartesianTree::with_tie_break(&v, tie_break);
+            let build = |values: &[u64]| {
+                tree::OwnedCartesianTree::from_vec_with(values.to_vec(), tie_break, u64::cmp)
+            };
+            for mid in [0, 1, 2, 17, 250, 499, 500] {
+                let tree = build(&v[..mid]).concat_with(build(&v[mid..]), tie_break, u64::cmp);
+                assert_eq!(tree.values(), v.as_slice());
+                assert!(tree.iter_pre_order().eq(expected.iter_pre_order()));
+                assert!(tree.min_spans().eq(expected.min_spans()));
+                assert_eq!(tree.signature(), expected.signature());
+                assert_eq!(
+                    tree.range_min(mid / 2, 400),
+                    expected.range_min(mid / 2, 400)
+                );
+            }
+        }
+
+        // Appending one chunk at a time
+        let mut tree = tree::OwnedCartesianTree::from(Vec::new());
+        for chunk in v.chunks(37) {
+            tree = tree.concat(chunk.to_vec().into());
+        }
+        let expected = tree::CartesianTree::from(&v[..]);
+        assert!(tree.iter_pre_order().eq(expected.iter_pre_order()));
+        assert!(tree.min_spans().eq(expected.min_spans()));
+        for i in (0..v.len()).step_by(7) {
+            assert_eq!(tree.depth(i), expected.depth(i));
+        }
+
+        // Appending one value at a time only touches the nodes whose
+        // subtrees change, which is a single node for decreasing values
+        let decreasing: Vec<u64> = (0..200_000).rev().collect();
+        let constant = vec![7u64; 200_000];
+        let increasing: Vec<u64> = (0..2_000).collect();
+        for values in [decreasing, constant, increasing, v] {
+            let mut tree = tree::OwnedCartesianTree::from(Vec::new());
+            for &value in &values {
+                tree = tree.concat(vec![value].into());
+            }
+            let expected = tree::CartesianTree::from(&values[..]);
+            assert!(tree.iter_pre_order().eq(expected.iter_pre_order()));
+            assert!(tree.min_spans().eq(expected.min_spans()));
+        }
+    }
+
+    #[test]
+    fn test_succinct_rmq() {
+        let random = pseudo_random_array(3000, 50);
+        let sorted: Vec<u64> = (0..3000).collect();
+        let reversed: Vec<u64> = (0..3000).rev().collect();
+        let constant = vec![7u64; 3000];
+        for v in [random, sorted, reversed, constant] {
+            for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+                let naive = match tie_break {
+                    tree::TieBreak::Leftmost => naive_leftmost_rmq::<u64>,
+                    tree::TieBreak::Rightmost => naive_rmq::<u64>,
+                };
+                let rmq = succinct::SuccinctRmq::with_tie_break(&v, tie_break);
+                let tree = tree::CartesianTree::with_tie_break(&v, tie_break);
+                let from_tree = succinct::SuccinctRmq::from(&tree);
+                assert_eq!(rmq.len(), v.len());
+                for i in (0..v.len()).step_by(11) {
+                    for j in (i + 1..=v.len()).step_by(23) {
+                        let min_idx = naive(&v, i, j);
+                        assert_eq!(rmq.query(i..j), min_idx);
+                        assert_eq!(from_tree.query(i..j), min_idx);
+                    }
+                    assert_eq!(rmq.query(i..i + 1), i);
+                    assert_eq!(rmq.query(i..v.len()), naive(&v, i, v.len()));
+                }
+            }
+        }
+
+        let small = [5, 1, 4, 1, 3];
+        let rmq = succinct::SuccinctRmq::from(small.as_ref());
+        for i in 0..small.len() {
+            for j in i + 1..=small.len() {
+                assert_eq!(rmq.query(i..j), naive_rmq(&small, i, j));
+            }
+        }
+        assert!(succinct::SuccinctRmq::from(Vec::<u64>::new().as_slice()).is_empty());
+    }
+
+    #[test]
+    fn test_compact_indexes() {
+        use std::mem::size_of;
+
+        // Missing links take no extra room
+        assert_eq!(size_of::<Option<tree::CartesianNodeIdx<u32>>>(), 4);
+        assert_eq!(size_of::<tree::CartesianTreeNode<u32>>(), 20);
+
+        let v = pseudo_random_array(1000, 40);
+        for tie_break in [tree::TieBreak::Leftmost, tree::TieBreak::Rightmost] {
+            let expected = tree::CartesianTree::with_tie_break(&v, tie_break);
+            let compact =
+                tree::CartesianTree::<_, u32>::try_from_slice_with(&v, tie_break, u64::cmp)
+                    .unwrap();
+            assert!(compact.iter_pre_order().eq(expected.iter_pre_order()));
+            assert!(compact.min_spans().eq(expected.min_spans()));
+            assert_eq!(compact.signature(), expected.signature());
+            for i in (0..v.len()).step_by(17) {
+                for j in (i..v.len()).step_by(31) {
+                    assert_eq!(compact.range_min(i, j), expected.range_min(i, j));
+                }
+            }
+            let root = compact.root().unwrap();
+            assert_eq!(root.array_index(), expected.root().unwrap().array_index());
+            assert_eq!(
+                root.left().map(|left| left.subtree_range()),
+                Some(0..root.array_index())
+            );
+
+            let owned = tree::OwnedCartesianTree::<_, u64>::try_from_vec_with(
+                v.clone(),
+                tie_break,
+                u64::cmp,
+            )
+            .unwrap();
+            assert!(owned.iter_in_order().eq(expected.iter_in_order()));
+        }
+
+        let (prefix, suffix) = v.split_at(400);
+        let split = |values: &[u64]| {
+            tree::OwnedCartesianTree::<_, u32>::try_from_vec_with(
+                values.to_vec(),
+                tree::TieBreak::default(),
+                u64::cmp,
+            )
+            .unwrap()
+        };
+        let combined = split(prefix).concat(split(suffix));
+        let expected = tree::CartesianTree::from(&v[..]);
+        assert!(combined.min_spans().eq(expected.min_spans()));
+        assert_eq!(combined.range_min(10, 900), expected.range_min(10, 900));
+
+        // An array of zero sized values takes no memory, however long it is
+        #[cfg(target_pointer_width = "64")]
+        {
+            let too_long = vec![(); (1 << 31) + 1];
+            let error = tree::CartesianTree::<_, u32>::try_from_slice_with(
+                &too_long,
+                tree::TieBreak::default(),
+                <()>::cmp,
+            )
+            .unwrap_err();
+            assert_eq!(error.len, (1 << 31) + 1);
+            assert_eq!(error.max_len, 1 << 31);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_saved_shapes() {
+        use super::persist::{ShapeError, TreeShape};
+        use serde_json::{json, Value};
+
+        fn save<I: super::NodeIndex>(shape: &TreeShape<I>) -> Value {
+            serde_json::to_value(shape).unwrap()
+        }
+
+        fn load(saved: Value) -> TreeShape {
+            serde_json::from_value(saved).unwrap()
+        }
+
+        for len in [0, 1, 2, 7, 500] {
+            let v = pseudo_random_array(len, 20);
+            let expected = tree::CartesianTree::from(&v[..]);
+            let shape = load(save(&expected.shape()));
+            assert_eq!(shape.len(), len);
+            let loaded = tree::CartesianTree::from_shape(&v, shape.clone()).unwrap();
+            assert!(loaded.iter_pre_order().eq(expected.iter_pre_order()));
+            assert_eq!(loaded.signature(), expected.signature());
+            for i in 0..len {
+                assert_eq!(loaded.depth(i), expected.depth(i));
+                for j in (i..len).step_by(7) {
+                    assert_eq!(loaded.range_min(i, j), expected.range_min(i, j));
+                }
+            }
+            let owned = tree::OwnedCartesianTree::from_vec_and_shape(v.clone(), shape).unwrap();
+            assert!(owned.iter_in_order().eq(expected.iter_in_order()));
+        }
+
+        // Max trees and compact indexes round trip as well
+        let v = pseudo_random_array(300, 50);
+        let expected = tree::CartesianTree::max_from(&v);
+        let shape = load(save(&expected.shape()));
+        let loaded = tree::CartesianTree::from_shape_by(&v, shape, |a, b| b.cmp(a)).unwrap();
+        assert_eq!(loaded.range_min(10, 250), expected.range_min(10, 250));
+        let compact = tree::CartesianTree::<_, u32>::try_from_slice_with(
+            &v,
+            tree::TieBreak::default(),
+            u64::cmp,
+        )
+        .unwrap();
+        let shape: TreeShape<u32> = serde_json::from_value(save(&compact.shape())).unwrap();
+        let loaded = tree::CartesianTree::from_shape(&v, shape).unwrap();
+        assert!(loaded.min_spans().eq(compact.min_spans()));
+
+        // A shape only fits an array of the same length, over which it is heap ordered
+        let increasing = [1, 2, 3];
+        let shape = tree::CartesianTree::from(&increasing[..]).shape();
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&increasing[..2], shape.clone()),
+            Err(ShapeError::LengthMismatch { len: 2, nodes: 3 })
+        ));
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&[3, 2, 1], shape.clone()),
+            Err(ShapeError::NotHeapOrdered(1))
+        ));
+
+        // Corrupted links do not load
+        let mut corrupted = save(&shape);
+        corrupted["nodes"][2]["parent_idx"] = Value::Null;
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&increasing, load(corrupted)),
+            Err(ShapeError::BadNode(1))
+        ));
+        let mut corrupted = save(&shape);
+        corrupted["root_idx"] = json!(2);
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&increasing, load(corrupted)),
+            Err(ShapeError::BadRoot)
+        ));
+
+        // Links are saved as plain indexes, without the spans of the subtrees
+        let saved = save(&tree::CartesianTree::from(&[2, 1, 3][..]).shape());
+        assert_eq!(saved["root_idx"], json!(1));
+        assert_eq!(saved["nodes"][0]["parent_idx"], json!(1));
+        assert_eq!(saved["nodes"][1]["left_child_idx"], json!(0));
+        assert_eq!(saved["nodes"][1]["right_child_idx"], json!(2));
+        assert!(saved["nodes"][1].get("span_start").is_none());
+        let mut corrupted = save(&shape);
+        corrupted["root_idx"] = json!(u64::MAX);
+        assert!(serde_json::from_value::<TreeShape>(corrupted).is_err());
+
+        // Links that reach a node twice, or miss one, do not load either
+        let mut corrupted = save(&shape);
+        corrupted["nodes"][1]["left_child_idx"] = json!(2);
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&increasing, load(corrupted)),
+            Err(ShapeError::BadNode(2))
+        ));
+        let mut corrupted = save(&shape);
+        corrupted["nodes"][1]["right_child_idx"] = Value::Null;
+        corrupted["nodes"][2]["parent_idx"] = Value::Null;
+        assert!(matches!(
+            tree::CartesianTree::from_shape(&increasing, load(corrupted)),
+            Err(ShapeError::BadNode(2))
+        ));
+
+        // The loaded tree builds its LCA structure from the links
+        let loaded = tree::CartesianTree::from_shape(&increasing, load(save(&shape))).unwrap();
+        assert_eq!(loaded.range_min(1, 2), (1, &2));
+        assert_eq!(loaded.depth(2), 2);
+
+        let v = pseudo_random_array(3000, 100);
+        let rmq = succinct::SuccinctRmq::from(v.as_slice());
+        let loaded: succinct::SuccinctRmq =
+            serde_json::from_str(&serde_json::to_string(&rmq).unwrap()).unwrap();
+        assert_eq!(loaded.len(), rmq.len());
+        for start in (0..v.len()).step_by(37) {
+            for end in (start + 1..=v.len()).step_by(91) {
+                assert_eq!(loaded.query(start..end), rmq.query(start..end));
+            }
+        }
+
+        // Parentheses that do not come from a stack do not load, and
+        // neither do lengths that disagree with the parentheses
+        let mut saved = serde_json::to_value(succinct::SuccinctRmq::from(&[2, 1][..])).unwrap();
+        assert_eq!(saved["bits"], json!([0b001011]));
+        assert_eq!(saved["bit_len"], json!(6));
+        let mut unbalanced = saved.clone();
+        unbalanced["bits"] = json!([0b100011]);
+        assert!(serde_json::from_value::<succinct::SuccinctRmq>(unbalanced).is_err());
+        saved["bit_len"] = json!(4);
+        assert!(serde_json::from_value::<succinct::SuccinctRmq>(saved).is_err());
+    }
 }
@@ -0,0 +1,139 @@
+//! Cartesian Tree Matching
+//!
+//! Finds every window of a text whose cartesian tree has the same shape
+//! as that of a pattern, in `O(n + m)` time. This is the algorithm of
+//! [Park et al.](https://arxiv.org/abs/1905.08974).
+//!
+//! Instead of comparing trees, we compare parent-distance representations.
+//! The parent distance of the value at index `i` is `i - j`, where `j` is
+//! the index of the closest earlier value that stays an ancestor of `i`
+//! when we build the tree, or 0 if there is no such value. These are the
+//! values left on the stack right below `i` during construction, so two
+//! arrays have the same shape exactly when they have the same parent
+//! distances.
+//!
+//! Unlike a cartesian tree number, the parent distance of a value only
+//! depends on the values before it. Within a window of the text, it stays
+//! the same as in the whole text, unless it reaches past the start of the
+//! window, in which case it becomes 0. This lets us compare the pattern
+//! against the text one value at a time, and fall back on a KMP style
+//! failure function whenever a comparison fails.
+
+use crate::nearest::nearest_left_by;
+use crate::tree::TieBreak;
+
+/// Searches texts for windows that have the same
+/// cartesian tree shape as a fixed pattern.
+///
+/// ```rust
+/// use cartesian_tree::CartesianTreeMatcher;
+///
+/// // A dip followed by a recovery that ends below the starting price
+/// let pattern = [30, 10, 20];
+/// let prices = [5, 3, 4, 9, 1, 7, 8, 2, 6];
+/// let matcher = CartesianTreeMatcher::new(&pattern);
+/// assert_eq!(matcher.find_all(&prices), vec![0, 3, 6]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CartesianTreeMatcher {
+    /// The parent distances of the pattern
+    pattern_distances: Vec<usize>,
+
+    /// `failure[q]` is the length of the longest proper prefix of
+    /// `pattern[..=q]` that has the same shape as a suffix of it
+    failure: Vec<usize>,
+
+    /// Decides which of several equal values is the ancestor of the others
+    tie_break: TieBreak,
+}
+
+impl CartesianTreeMatcher {
+    /// Prepares to search for the shape of `pattern`, with
+    /// equal values resolved like [`crate::CartesianTree::from`]
+    pub fn new<T: Ord>(pattern: &[T]) -> Self {
+        Self::with_tie_break(pattern, TieBreak::default())
+    }
+
+    /// Prepares to search for the shape of `pattern`, with
+    /// equal values resolved according to the given policy
+    pub fn with_tie_break<T: Ord>(pattern: &[T], tie_break: TieBreak) -> Self {
+        let pattern_distances = parent_distances(pattern, tie_break);
+        let len = pattern_distances.len();
+        let mut failure = vec![0; len];
+        let mut matched = 0;
+        for q in 1..len {
+            while matched > 0
+                && distance_within(pattern_distances[q], matched) != pattern_distances[matched]
+            {
+                matched = failure[matched - 1];
+            }
+            if distance_within(pattern_distances[q], matched) == pattern_distances[matched] {
+                matched += 1;
+            }
+            failure[q] = matched;
+        }
+        CartesianTreeMatcher {
+            pattern_distances,
+            failure,
+            tie_break,
+        }
+    }
+
+    /// The number of values in the pattern
+    pub fn pattern_len(&self) -> usize {
+        self.pattern_distances.len()
+    }
+
+    /// Finds the start of every window of `text` whose cartesian tree
+    /// has the same shape as that of the pattern. Windows may overlap.
+    /// An empty pattern matches at every offset.
+    pub fn find_all<T: Ord>(&self, text: &[T]) -> Vec<usize> {
+        let len = self.pattern_len();
+        if len == 0 {
+            return (0..=text.len()).collect();
+        }
+        let text_distances = parent_distances(text, self.tie_break);
+        let mut matches = Vec::new();
+        let mut matched = 0;
+        for (i, &distance) in text_distances.iter().enumerate() {
+            while matched > 0
+                && distance_within(distance, matched) != self.pattern_distances[matched]
+            {
+                matched = self.failure[matched - 1];
+            }
+            // Every single value has the same shape as the first value
+            // of the pattern, so this always holds when `matched == 0`
+            if distance_within(distance, matched) == self.pattern_distances[matched] {
+                matched += 1;
+            }
+            if matched == len {
+                matches.push(i + 1 - len);
+                matched = self.failure[len - 1];
+            }
+        }
+        matches
+    }
+}
+
+/// The parent distance of a value in a window in which `preceding`
+/// values come before it. Distances that reach past the start of
+/// the window become 0.
+fn distance_within(distance: usize, preceding: usize) -> usize {
+    if distance > preceding {
+        0
+    } else {
+        distance
+    }
+}
+
+/// Computes the parent distance of every value. The parent of a value
+/// is the nearest earlier value that stays its ancestor.
+fn parent_distances<T: Ord>(values: &[T], tie_break: TieBreak) -> Vec<usize> {
+    nearest_left_by(values, |earlier, later| {
+        tie_break.earlier_wins(earlier.cmp(later))
+    })
+    .into_iter()
+    .enumerate()
+    .map(|(i, parent)| parent.map_or(0, |parent| i - parent))
+    .collect()
+}
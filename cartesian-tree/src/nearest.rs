@@ -0,0 +1,81 @@
+//! All Nearest Smaller Values
+//!
+//! For every index of an array, finds the closest index on either side that
+//! holds a smaller (or greater) value. This is what the stack does when we
+//! build a cartesian tree: once we have popped every value that is at least
+//! as large as the value we are adding, the value atop the stack is the
+//! nearest smaller value to its left. Each index is pushed and popped at
+//! most once, so each of these functions runs in linear time.
+//!
+//! ```rust
+//! use cartesian_tree::nearest::{nearest_smaller_left, nearest_smaller_right};
+//!
+//! let v = [3, 1, 4, 1, 5];
+//! assert_eq!(nearest_smaller_left(&v), vec![None, None, Some(1), None, Some(3)]);
+//! assert_eq!(nearest_smaller_right(&v), vec![Some(1), None, Some(3), None, None]);
+//! ```
+
+/// For every index `i`, the largest `j < i` with `values[j] < values[i]`
+pub fn nearest_smaller_left<T: Ord>(values: &[T]) -> Vec<Option<usize>> {
+    nearest_left_by(values, |earlier, later| earlier < later)
+}
+
+/// For every index `i`, the smallest `j > i` with `values[j] < values[i]`
+pub fn nearest_smaller_right<T: Ord>(values: &[T]) -> Vec<Option<usize>> {
+    nearest_right_by(values, |later, earlier| later < earlier)
+}
+
+/// For every index `i`, the largest `j < i` with `values[j] > values[i]`
+pub fn nearest_greater_left<T: Ord>(values: &[T]) -> Vec<Option<usize>> {
+    nearest_left_by(values, |earlier, later| earlier > later)
+}
+
+/// For every index `i`, the smallest `j > i` with `values[j] > values[i]`
+pub fn nearest_greater_right<T: Ord>(values: &[T]) -> Vec<Option<usize>> {
+    nearest_right_by(values, |later, earlier| later > earlier)
+}
+
+/// For every index `i`, finds the largest `j < i` such that
+/// `beats(&values[j], &values[i])`. `beats` should be a strict or
+/// non-strict ordering, so that whatever an index beats, the
+/// indexes it loses to beat as well.
+pub(crate) fn nearest_left_by<T, F>(values: &[T], mut beats: F) -> Vec<Option<usize>>
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let mut stack: Vec<usize> = Vec::with_capacity(values.len());
+    let mut nearest = Vec::with_capacity(values.len());
+    for (i, value) in values.iter().enumerate() {
+        while let Some(&top) = stack.last() {
+            if beats(&values[top], value) {
+                break;
+            }
+            stack.pop();
+        }
+        nearest.push(stack.last().cloned());
+        stack.push(i);
+    }
+    nearest
+}
+
+/// For every index `i`, finds the smallest `j > i` such
+/// that `beats(&values[j], &values[i])`. This is the mirror
+/// image of [`nearest_left_by`].
+pub(crate) fn nearest_right_by<T, F>(values: &[T], mut beats: F) -> Vec<Option<usize>>
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let mut stack: Vec<usize> = Vec::with_capacity(values.len());
+    let mut nearest = vec![None; values.len()];
+    for (i, value) in values.iter().enumerate().rev() {
+        while let Some(&top) = stack.last() {
+            if beats(&values[top], value) {
+                break;
+            }
+            stack.pop();
+        }
+        nearest[i] = stack.last().cloned();
+        stack.push(i);
+    }
+    nearest
+}
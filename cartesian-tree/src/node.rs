@@ -0,0 +1,96 @@
+//! Navigating a Cartesian Tree
+//!
+//! A [`NodeRef`] is a cheap handle to a single node of a [`CartesianTree`](crate::CartesianTree).
+//! It lets us walk the tree from the root down, or from any node up, so that
+//! we can implement our own algorithms on top of the linear time construction.
+//! For instance, the root of any subtree is the minimum of the range of the
+//! array that the subtree spans, which is what divide and conquer algorithms
+//! over min positions rely on.
+
+use crate::index::NodeIndex;
+use crate::tree::{CartesianNodeIdx, Links};
+use std::ops::Range;
+
+/// A handle to a single node in a cartesian tree. The node at
+/// index `i` of the underlying array is the one that represents
+/// the value at that index.
+pub struct NodeRef<'t, T, I: NodeIndex = usize> {
+    values: &'t [T],
+    links: &'t Links<I>,
+    idx: usize,
+}
+
+impl<'t, T, I: NodeIndex> NodeRef<'t, T, I> {
+    pub(crate) fn new(values: &'t [T], links: &'t Links<I>, idx: usize) -> Self {
+        NodeRef { values, links, idx }
+    }
+
+    fn node_at(&self, idx: &Option<CartesianNodeIdx<I>>) -> Option<Self> {
+        idx.as_ref()
+            .map(|idx| NodeRef::new(self.values, self.links, idx.get()))
+    }
+
+    /// The left child of this node. Its subtree spans the part of this
+    /// node's range that comes before the node.
+    pub fn left(&self) -> Option<Self> {
+        self.node_at(&self.links.nodes[self.idx].left_child_idx)
+    }
+
+    /// The right child of this node. Its subtree spans the part of this
+    /// node's range that comes after the node.
+    pub fn right(&self) -> Option<Self> {
+        self.node_at(&self.links.nodes[self.idx].right_child_idx)
+    }
+
+    /// The parent of this node. This is `None` for the root
+    pub fn parent(&self) -> Option<Self> {
+        self.node_at(&self.links.nodes[self.idx].parent_idx)
+    }
+
+    /// The array value that this node represents
+    pub fn value(&self) -> &'t T {
+        &self.values[self.idx]
+    }
+
+    /// The index, in the underlying array, of the value that this node represents
+    pub fn array_index(&self) -> usize {
+        self.idx
+    }
+
+    /// The number of edges between this node and the root
+    pub fn depth(&self) -> usize {
+        self.links.lca().depth(self.idx)
+    }
+
+    /// The range of the underlying array that the subtree rooted at
+    /// this node spans. This node holds the minimum of that range.
+    pub fn subtree_range(&self) -> Range<usize> {
+        self.links.nodes[self.idx].span()
+    }
+}
+
+impl<'t, T, I: NodeIndex> Clone for NodeRef<'t, T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'t, T, I: NodeIndex> Copy for NodeRef<'t, T, I> {}
+
+/// Two handles are equal when they point to the same node of the same tree
+impl<'t, T, I: NodeIndex> PartialEq for NodeRef<'t, T, I> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.links, other.links) && self.idx == other.idx
+    }
+}
+
+impl<'t, T, I: NodeIndex> Eq for NodeRef<'t, T, I> {}
+
+impl<'t, T: std::fmt::Debug, I: NodeIndex> std::fmt::Debug for NodeRef<'t, T, I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeRef")
+            .field("array_index", &self.idx)
+            .field("value", self.value())
+            .finish()
+    }
+}
@@ -0,0 +1,289 @@
+//! Cartesian Tree Numbers
+//!
+//! When building a cartesian tree, we push every node onto the stack once,
+//! and pop some of them off. Writing down a `1` for every push and a `0` for
+//! every pop gives us a bit-string that fully describes the shape of the
+//! tree. The number formed by this bit-string, whose first action is its
+//! lowest bit, is the tree's cartesian tree number.
+//!
+//! The last action is always a push, so the highest set bit of the number
+//! marks the end of the bit-string. Numbers of arrays of different lengths
+//! are therefore always different, and comparing two numbers compares the
+//! shapes of the trees they came from.
+//!
+//! An array of length `n` needs up to `2n - 1` actions. Numbers of up to 128
+//! actions, which covers every array of up to 64 items, fit in a `u128`. Longer
+//! numbers are kept in a vector of 64 bit words.
+
+use crate::tree::TieBreak;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The cartesian tree number of an array of any length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CartesianTreeNumber(Repr);
+
+/// Numbers that fit in a `u128` are always `Small`, so
+/// that each number has a single representation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Small(u128),
+
+    /// The words of the number, lowest word first. The last word is never 0
+    Large(Vec<u64>),
+}
+
+impl CartesianTreeNumber {
+    /// The number of push and pop actions that form this number
+    pub fn bit_len(&self) -> usize {
+        match &self.0 {
+            Repr::Small(number) => (u128::BITS - number.leading_zeros()) as usize,
+            Repr::Large(words) => {
+                let top = words[words.len() - 1];
+                (words.len() - 1) * 64 + (u64::BITS - top.leading_zeros()) as usize
+            }
+        }
+    }
+
+    /// Whether the action at the given offset was a push
+    fn is_push(&self, offset: usize) -> bool {
+        match &self.0 {
+            Repr::Small(number) => number >> offset & 1 == 1,
+            Repr::Large(words) => words[offset / 64] >> (offset % 64) & 1 == 1,
+        }
+    }
+
+    /// The actions that form this number, first action first.
+    /// Each action is `true` for a push and `false` for a pop.
+    pub(crate) fn actions(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.bit_len()).map(move |offset| self.is_push(offset))
+    }
+
+    /// Replays the actions that form this number. For every push, we
+    /// return the height of the stack right before the push. Returns
+    /// `None` if the number is not a valid sequence of actions, that is
+    /// if it pops from an empty stack.
+    pub(crate) fn push_heights(&self) -> Option<Vec<usize>> {
+        let mut heights = Vec::new();
+        let mut height = 0usize;
+        for is_push in self.actions() {
+            if is_push {
+                heights.push(height);
+                height += 1;
+            } else {
+                height = height.checked_sub(1)?;
+            }
+        }
+        Some(heights)
+    }
+
+    /// The number as a `u64`, if it fits in one
+    pub fn to_u64(&self) -> Option<u64> {
+        match &self.0 {
+            Repr::Small(number) => u64::try_from(*number).ok(),
+            Repr::Large(_) => None,
+        }
+    }
+
+    /// The number as a `u128`, if it fits in one
+    pub fn to_u128(&self) -> Option<u128> {
+        match &self.0 {
+            Repr::Small(number) => Some(*number),
+            Repr::Large(_) => None,
+        }
+    }
+}
+
+impl From<u64> for CartesianTreeNumber {
+    fn from(number: u64) -> Self {
+        CartesianTreeNumber(Repr::Small(number as u128))
+    }
+}
+
+impl From<u128> for CartesianTreeNumber {
+    fn from(number: u128) -> Self {
+        CartesianTreeNumber(Repr::Small(number))
+    }
+}
+
+/// Numbers are ordered by their numeric value. Every `Large`
+/// number is bigger than every `Small` one, and the bigger of two
+/// `Large` numbers either has more words or a bigger top word.
+impl Ord for CartesianTreeNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.0, &other.0) {
+            (Repr::Small(a), Repr::Small(b)) => a.cmp(b),
+            (Repr::Small(_), Repr::Large(_)) => Ordering::Less,
+            (Repr::Large(_), Repr::Small(_)) => Ordering::Greater,
+            (Repr::Large(a), Repr::Large(b)) => a
+                .len()
+                .cmp(&b.len())
+                .then_with(|| a.iter().rev().cmp(b.iter().rev())),
+        }
+    }
+}
+
+impl PartialOrd for CartesianTreeNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Prints the push/pop bit-string, with the last action first
+impl fmt::Binary for CartesianTreeNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::Small(number) => fmt::Binary::fmt(number, f),
+            Repr::Large(words) => {
+                let mut words = words.iter().rev();
+                if let Some(top) = words.next() {
+                    write!(f, "{:b}", top)?;
+                }
+                words.try_for_each(|word| write!(f, "{:064b}", word))
+            }
+        }
+    }
+}
+
+/// Accumulates push and pop actions into a cartesian tree number.
+/// We fill a `u128` first, and only move to a vector of words
+/// once the actions no longer fit.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NumberBuilder {
+    small: u128,
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl NumberBuilder {
+    /// Records the next action
+    pub(crate) fn push_action(&mut self, is_push: bool) {
+        if self.len < 128 {
+            self.small |= (is_push as u128) << self.len;
+        } else {
+            if self.len == 128 {
+                self.words = vec![self.small as u64, (self.small >> 64) as u64];
+            }
+            if self.len % 64 == 0 {
+                self.words.push(0);
+            }
+            let last = self.words.len() - 1;
+            self.words[last] |= (is_push as u64) << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn finish(self) -> CartesianTreeNumber {
+        if self.len <= 128 {
+            CartesianTreeNumber(Repr::Small(self.small))
+        } else {
+            CartesianTreeNumber(Repr::Large(self.words))
+        }
+    }
+}
+
+/// The right spine of a cartesian tree under construction. This runs
+/// the stack based construction without linking up any nodes, which is
+/// all we need to follow the actions that build a tree.
+#[derive(Debug, Clone)]
+pub(crate) struct ShapeStack<T> {
+    stack: Vec<T>,
+    tie_break: TieBreak,
+}
+
+impl<T: Ord> ShapeStack<T> {
+    pub(crate) fn new(tie_break: TieBreak) -> Self {
+        ShapeStack {
+            stack: Vec::new(),
+            tie_break,
+        }
+    }
+
+    /// Adds the next value, and returns the
+    /// number of values it popped off the stack
+    pub(crate) fn push(&mut self, value: T) -> usize {
+        let mut pops = 0;
+        while let Some(top) = self.stack.last() {
+            if self.tie_break.earlier_wins(top.cmp(&value)) {
+                break;
+            }
+            self.stack.pop();
+            pops += 1;
+        }
+        self.stack.push(value);
+        pops
+    }
+}
+
+/// Computes the cartesian tree number of a stream of values, one value
+/// at a time. This only keeps track of the right spine of the tree, so
+/// it never builds the tree itself.
+///
+/// ```rust
+/// use cartesian_tree::{CartesianTree, SignatureBuilder};
+///
+/// let v = [93, 84, 33, 64, 62, 83, 63];
+/// let mut builder = SignatureBuilder::new();
+/// v.iter().for_each(|value| {
+///     builder.push(value);
+/// });
+/// let tree: CartesianTree<'_, _> = v.as_ref().into();
+/// assert_eq!(builder.finish(), tree.signature());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SignatureBuilder<T> {
+    spine: ShapeStack<T>,
+    number: NumberBuilder,
+}
+
+impl<T: Ord> Default for SignatureBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> SignatureBuilder<T> {
+    /// A builder that resolves equal values like [`crate::CartesianTree::from`]
+    pub fn new() -> Self {
+        Self::with_tie_break(TieBreak::default())
+    }
+
+    /// A builder that resolves equal values according to the given policy
+    pub fn with_tie_break(tie_break: TieBreak) -> Self {
+        SignatureBuilder {
+            spine: ShapeStack::new(tie_break),
+            number: NumberBuilder::default(),
+        }
+    }
+
+    /// Adds the next value, and returns the number of values that it
+    /// popped off the stack. Two streams have the same shape exactly
+    /// when their values pop the same number of values at every step.
+    pub fn push(&mut self, value: T) -> usize {
+        let pops = self.spine.push(value);
+        (0..pops).for_each(|_| self.number.push_action(false));
+        self.number.push_action(true);
+        pops
+    }
+
+    /// The cartesian tree number of the values pushed so far
+    pub fn finish(self) -> CartesianTreeNumber {
+        self.number.finish()
+    }
+}
+
+/// Checks whether two arrays have cartesian trees of the same shape, that
+/// is, whether the minimum of every range of `a` is at the same index as
+/// the minimum of that range of `b`. We run the stack based construction
+/// on both arrays in lockstep, and stop at the first step at which the two
+/// arrays pop a different number of values. Neither tree is ever built.
+pub fn same_shape<T: Ord, U: Ord>(a: &[T], b: &[U]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut a_spine = ShapeStack::new(TieBreak::default());
+    let mut b_spine = ShapeStack::new(TieBreak::default());
+    a.iter()
+        .zip(b)
+        .all(|(x, y)| a_spine.push(x) == b_spine.push(y))
+}
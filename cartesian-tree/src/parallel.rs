@@ -0,0 +1,186 @@
+//! Parallel Construction
+//!
+//! The stack based construction looks at the values one at a time, so it
+//! cannot keep more than one core busy. To build the tree of a very large
+//! array, we instead cut the array in two, build the trees of both halves
+//! in parallel, and stitch them together. We keep cutting the halves until
+//! they are small enough to build sequentially.
+//!
+//! Stitching only looks at the right spine of the left tree and the left
+//! spine of the right tree, the same way [`CartesianTree::concat`] does,
+//! and ends up with exactly the tree that the sequential construction
+//! builds.
+//!
+//! Stitching is still sequential, and takes time proportional to the
+//! number of nodes it pops off the right spine of the left tree, plus the
+//! number of nodes it walks up the left spine of the right tree. When it
+//! pops the whole left tree, it walks all the way up that spine, to
+//! stretch the subtrees on it over the left tree. Most arrays keep these
+//! spines short, but an array that rises to a peak in the middle and
+//! falls after it has spines that hold every value, as does a decreasing
+//! or constant array, and the last stitch then takes linear time on one
+//! thread. Once we are done stitching, we also walk down the right spine
+//! of the finished tree, to stretch the subtrees on it to the end of the
+//! array. An increasing array has every value on that spine, so this walk
+//! takes linear time on one thread as well.
+//!
+//! With the `rayon` feature, the lowest common ancestor structure of any
+//! tree writes the Euler tours of large subtrees in parallel, when it is
+//! built on the first range min query. We know where the tour of every
+//! subtree starts from the number of nodes to its left, so the tours of
+//! the two subtrees of a node do not wait on each other. The `±1 RMQ`
+//! tables over the depths are still built in one pass. Small trees write
+//! their tours on the calling thread, since spawning tasks for them costs
+//! more than the tour itself.
+
+use crate::index::{check_width, IndexWidthError, NodeIndex};
+use crate::lca::{LowestCommonAncestor, TourPart};
+use crate::tree::{
+    close_outer_spines, stitch, CartesianNodeIdx, CartesianTree, CartesianTreeNode, Links, Nodes,
+    TieBreak,
+};
+use std::cmp::Ordering;
+
+/// Below this many values, we build a tree or write its Euler tour on
+/// one thread
+pub(crate) const SEQUENTIAL_CUTOFF: usize = 1 << 14;
+
+impl<'a, T: Ord + Sync> CartesianTree<'a, T> {
+    /// Builds the same tree as [`CartesianTree::from`], splitting
+    /// the work across the threads of the current rayon pool.
+    pub fn par_from(underlying: &'a [T]) -> Self {
+        Self::par_from_slice_with(underlying, TieBreak::default(), T::cmp)
+    }
+}
+
+impl<'a, T: Sync> CartesianTree<'a, T> {
+    /// Builds the same tree as [`CartesianTree::from_slice_with`],
+    /// splitting the work across the threads of the current rayon pool.
+    pub fn par_from_slice_with<F>(underlying: &'a [T], tie_break: TieBreak, cmp: F) -> Self
+    where
+        F: Fn(&T, &T) -> Ordering + Sync,
+    {
+        Self::par_build(underlying, tie_break, cmp)
+    }
+}
+
+impl<'a, T: Sync, I: NodeIndex> CartesianTree<'a, T, I> {
+    /// Builds the same tree as [`CartesianTree::try_from_slice_with`],
+    /// splitting the work across the threads of the current rayon pool.
+    pub fn try_par_from_slice_with<F>(
+        underlying: &'a [T],
+        tie_break: TieBreak,
+        cmp: F,
+    ) -> Result<Self, IndexWidthError>
+    where
+        F: Fn(&T, &T) -> Ordering + Sync,
+    {
+        check_width::<I>(underlying.len())?;
+        Ok(Self::par_build(underlying, tie_break, cmp))
+    }
+
+    fn par_build<F>(underlying: &'a [T], tie_break: TieBreak, cmp: F) -> Self
+    where
+        F: Fn(&T, &T) -> Ordering + Sync,
+    {
+        let (nodes, root_idx) =
+            link_nodes_in_parallel(underlying.len(), SEQUENTIAL_CUTOFF, &|top, new| {
+                tie_break.earlier_wins(cmp(&underlying[top], &underlying[new]))
+            });
+        Self::from_links(underlying, Links::new(nodes, root_idx))
+    }
+}
+
+/// Wires up the nodes of a tree with `len` nodes, like the sequential
+/// construction does, but builds the trees of ranges of more than
+/// `cutoff` nodes out of the trees of their two halves.
+pub(crate) fn link_nodes_in_parallel<I: NodeIndex, F>(
+    len: usize,
+    cutoff: usize,
+    stays_ancestor: &F,
+) -> (Nodes<I>, Option<CartesianNodeIdx<I>>)
+where
+    F: Fn(usize, usize) -> bool + Sync,
+{
+    let mut nodes = vec![CartesianTreeNode::default(); len];
+    let root = link_range(&mut nodes, 0, cutoff.max(1), stays_ancestor);
+    close_outer_spines(&mut nodes, root);
+    (nodes, root.map(CartesianNodeIdx::new))
+}
+
+/// Wires up `nodes`, the first of which is the node at index `offset`, and
+/// returns the root of their tree. The links between the nodes are final.
+/// So are their spans, except that the subtrees of the nodes on the outer
+/// spines may not reach the ends of the range yet. The subtree of the root
+/// does start at `offset`, though.
+fn link_range<I: NodeIndex, F>(
+    nodes: &mut [CartesianTreeNode<I>],
+    offset: usize,
+    cutoff: usize,
+    stays_ancestor: &F,
+) -> Option<usize>
+where
+    F: Fn(usize, usize) -> bool + Sync,
+{
+    let len = nodes.len();
+    if len <= cutoff {
+        let (linked, root_idx) = CartesianTree::<(), I>::link_nodes(len, |top, new| {
+            stays_ancestor(offset + top, offset + new)
+        });
+        for (node, linked) in nodes.iter_mut().zip(linked) {
+            *node = linked.shifted(offset);
+        }
+        return root_idx.map(|root| offset + root.get());
+    }
+    let mid = len / 2;
+    let (left, right) = nodes.split_at_mut(mid);
+    let (left_root, right_root) = rayon::join(
+        || link_range(left, offset, cutoff, stays_ancestor),
+        || link_range(right, offset + mid, cutoff, stays_ancestor),
+    );
+    // Both halves are non empty, so both have a root
+    let roots = (left_root.unwrap(), right_root.unwrap());
+    Some(stitch(nodes, offset, offset + mid, roots, stays_ancestor))
+}
+
+/// Writes the Euler tour of the tree rooted at `root`. Above the cutoff,
+/// we hand the smaller subtree of every node to another task, and keep
+/// walking down the larger one ourselves, so that path shaped trees do
+/// not overflow the call stack.
+pub(crate) fn walk_in_parallel<I: NodeIndex>(
+    nodes: &[CartesianTreeNode<I>],
+    root: usize,
+    tour: TourPart<'_, I>,
+) {
+    rayon::scope(|scope| walk_subtree(scope, nodes, root, 0, tour));
+}
+
+fn walk_subtree<'s, I: NodeIndex>(
+    scope: &rayon::Scope<'s>,
+    nodes: &'s [CartesianTreeNode<I>],
+    mut root: usize,
+    mut depth: usize,
+    mut tour: TourPart<'s, I>,
+) {
+    while tour.len() > SEQUENTIAL_CUTOFF {
+        let node = &nodes[root];
+        let (left, right) = tour.split(root, depth);
+        let mut children = [
+            (node.left_child_idx.as_ref(), left),
+            (node.right_child_idx.as_ref(), right),
+        ];
+        if children[0].1.len() > children[1].1.len() {
+            children.swap(0, 1);
+        }
+        let [smaller, larger] = children;
+        depth += 1;
+        if let (Some(child), smaller_tour) = smaller {
+            let child = child.get();
+            scope.spawn(move |scope| walk_subtree(scope, nodes, child, depth, smaller_tour));
+        }
+        // A subtree above the cutoff has at least one child
+        root = larger.0.unwrap().get();
+        tour = larger.1;
+    }
+    LowestCommonAncestor::walk(nodes, root, depth, tour);
+}
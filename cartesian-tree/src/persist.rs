@@ -0,0 +1,235 @@
+//! Saving and Loading Trees
+//!
+//! Building a cartesian tree takes a pass over the whole array, and compares
+//! its values along the way. When the same array is queried again and again,
+//! we would rather build the tree once, save it, and load it along with the
+//! array. A [`TreeShape`] holds the links of a tree without the values
+//! themselves, so it can be saved with serde on its own. It saves each link
+//! as the plain index of the node it points to, and leaves out the ranges
+//! that the subtrees span, which follow from the links. Loading it back
+//! takes the array it was built from, and checks that the two fit together:
+//! the shape must have one node per value, no more nodes than its index
+//! type can address, be a well formed tree whose in order traversal visits
+//! the values in order, and be heap ordered over the values.
+//!
+//! We do not save the lowest common ancestor structure. Checking that a
+//! saved one answers queries correctly costs about as much as building it
+//! again from the links, which the loaded tree does on its first range min
+//! query. For the same reason, [`FischerHeunRmq`](crate::FischerHeunRmq) and
+//! [`SparseTableRmq`](crate::SparseTableRmq) cannot be saved, and are built
+//! again from the array instead. The only range min index that can be saved
+//! is a [`SuccinctRmq`](crate::SuccinctRmq), which holds nothing but the
+//! parentheses that encode its tree, and checks that they are balanced when
+//! it is loaded.
+//!
+//! ```rust
+//! use cartesian_tree::{CartesianTree, TreeShape};
+//!
+//! let v = [93, 84, 33, 64, 62, 83, 63];
+//! let saved = serde_json::to_string(&CartesianTree::from(&v[..]).shape()).unwrap();
+//!
+//! let shape: TreeShape = serde_json::from_str(&saved).unwrap();
+//! let tree = CartesianTree::from_shape(&v, shape).unwrap();
+//! assert_eq!(tree.range_min(3, 6), (4, &62));
+//! ```
+
+use crate::index::{check_width, IndexWidthError, NodeIndex};
+use crate::tree::{CartesianNodeIdx, CartesianTree, Links, Nodes, OwnedCartesianTree};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The shape of a cartesian tree, without the values of the array
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct TreeShape<I: NodeIndex = usize> {
+    nodes: Nodes<I>,
+    root_idx: Option<CartesianNodeIdx<I>>,
+}
+
+/// The reasons a shape may not fit the array we attach it to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The shape has a different number of nodes than the array has values
+    LengthMismatch { len: usize, nodes: usize },
+
+    /// The shape has more nodes than its index type can address
+    TooLarge(IndexWidthError),
+
+    /// The root of the shape is missing, out of range, or has a parent
+    BadRoot,
+
+    /// The links of the node at this index are not those of a tree
+    /// whose in order traversal visits the nodes in order
+    BadNode(usize),
+
+    /// The value at this index is smaller than the value of its parent
+    NotHeapOrdered(usize),
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeError::LengthMismatch { len, nodes } => write!(
+                f,
+                "a shape with {} nodes does not fit an array of {} values",
+                nodes, len
+            ),
+            ShapeError::TooLarge(error) => error.fmt(f),
+            ShapeError::BadRoot => write!(f, "the shape does not have a proper root"),
+            ShapeError::BadNode(i) => write!(f, "node {} is not linked like a cartesian tree", i),
+            ShapeError::NotHeapOrdered(i) => {
+                write!(f, "value {} is smaller than the value of its parent", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+impl<I: NodeIndex> TreeShape<I> {
+    /// The number of nodes in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Makes sure that the nodes form a binary tree whose in order traversal
+    /// visits them in order, and works out the range that every subtree
+    /// spans along the way. Every child that we walk down to must have the
+    /// node we come from as its parent, and the root has no parent, so we
+    /// never walk down to a node twice, even if the links form a cycle.
+    fn link_spans(&mut self) -> Result<(), ShapeError> {
+        let len = self.nodes.len();
+        let root = match index_of(&self.root_idx) {
+            None if len == 0 => return Ok(()),
+            Some(root) if root < len && self.nodes[root].parent_idx.is_none() => root,
+            _ => return Err(ShapeError::BadRoot),
+        };
+
+        // Each entry holds a node and how many of its children we have
+        // already descended into. `next` is the node that the in order
+        // traversal should visit next.
+        let mut stack = vec![(root, 0u8)];
+        let mut next = 0;
+        while let Some(top) = stack.last_mut() {
+            let (idx, descended) = *top;
+            top.1 += 1;
+            let node = &self.nodes[idx];
+            let child = match descended {
+                0 => index_of(&node.left_child_idx),
+                1 => {
+                    if idx != next {
+                        return Err(ShapeError::BadNode(idx));
+                    }
+                    next += 1;
+                    index_of(&node.right_child_idx)
+                }
+                _ => {
+                    // Both subtrees are done, so we know where ours starts and ends
+                    let start = index_of(&node.left_child_idx)
+                        .map_or(idx, |left| self.nodes[left].span().start);
+                    let end = index_of(&node.right_child_idx)
+                        .map_or(idx + 1, |right| self.nodes[right].span().end);
+                    self.nodes[idx].set_span_start(start);
+                    self.nodes[idx].set_span_end(end);
+                    stack.pop();
+                    continue;
+                }
+            };
+            if let Some(child) = child {
+                if child >= len || index_of(&self.nodes[child].parent_idx) != Some(idx) {
+                    return Err(ShapeError::BadNode(idx));
+                }
+                stack.push((child, 0));
+            }
+        }
+
+        // Nodes that we could not reach from the root do not belong to the tree
+        if next != len {
+            return Err(ShapeError::BadNode(next));
+        }
+        Ok(())
+    }
+
+    /// Makes sure that the shape fits `values`, and
+    /// attaches it to them if it does
+    fn attach<'a, T, V, F>(
+        mut self,
+        values: V,
+        mut cmp: F,
+    ) -> Result<CartesianTree<'a, T, I, V>, ShapeError>
+    where
+        V: AsRef<[T]>,
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let slice = values.as_ref();
+        if slice.len() != self.nodes.len() {
+            return Err(ShapeError::LengthMismatch {
+                len: slice.len(),
+                nodes: self.nodes.len(),
+            });
+        }
+        check_width::<I>(slice.len()).map_err(ShapeError::TooLarge)?;
+        self.link_spans()?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(parent) = index_of(&node.parent_idx) {
+                if cmp(&slice[i], &slice[parent]) == Ordering::Less {
+                    return Err(ShapeError::NotHeapOrdered(i));
+                }
+            }
+        }
+        let links = Links::new(self.nodes, self.root_idx);
+        Ok(CartesianTree::from_links(values, links))
+    }
+}
+
+fn index_of<I: NodeIndex>(link: &Option<CartesianNodeIdx<I>>) -> Option<usize> {
+    link.as_ref().map(CartesianNodeIdx::get)
+}
+
+impl<'a, T, I: NodeIndex, V: AsRef<[T]>> CartesianTree<'a, T, I, V> {
+    /// The shape of this tree, which can be saved without the array
+    pub fn shape(&self) -> TreeShape<I> {
+        TreeShape {
+            nodes: self.links.nodes.clone(),
+            root_idx: self.links.root_idx.clone(),
+        }
+    }
+}
+
+impl<'a, T, I: NodeIndex> CartesianTree<'a, T, I> {
+    /// Attaches a saved shape to the array it was built from, after
+    /// checking that it fits the array. The shape must have one node per
+    /// value, and be heap ordered according to `cmp`.
+    pub fn from_shape_by<F>(
+        underlying: &'a [T],
+        shape: TreeShape<I>,
+        cmp: F,
+    ) -> Result<Self, ShapeError>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        shape.attach(underlying, cmp)
+    }
+}
+
+impl<'a, T: Ord, I: NodeIndex> CartesianTree<'a, T, I> {
+    /// Attaches the saved shape of a min tree to the array it was built from.
+    /// See [`CartesianTree::from_shape_by`].
+    pub fn from_shape(underlying: &'a [T], shape: TreeShape<I>) -> Result<Self, ShapeError> {
+        Self::from_shape_by(underlying, shape, T::cmp)
+    }
+}
+
+impl<T: Ord, I: NodeIndex> OwnedCartesianTree<T, I> {
+    /// Attaches the saved shape of a min tree to the array it was
+    /// built from, taking ownership of the array. See
+    /// [`CartesianTree::from_shape_by`].
+    pub fn from_vec_and_shape(underlying: Vec<T>, shape: TreeShape<I>) -> Result<Self, ShapeError> {
+        shape.attach(underlying, T::cmp)
+    }
+}
@@ -0,0 +1,186 @@
+//! Cartesian Tree Shapes
+//!
+//! There are exactly `C_n`, the `n`th Catalan number, differently shaped
+//! cartesian trees with `n` nodes. The cartesian tree numbers of these
+//! shapes are spread out over a range of about `4^n` values, so tables
+//! indexed by cartesian tree number are mostly empty. Here, we instead map
+//! each shape to a dense rank in `0..C_n`, and back.
+//!
+//! If we append one pop for each node left on the stack to the actions of
+//! a tree with `n` nodes, we get a sequence of `n` pushes and `n` pops in
+//! which no prefix has more pops than pushes. That is, a Dyck path. Every
+//! Dyck path of length `2n` is the path of exactly one tree, since dropping
+//! its trailing pops gives us back the actions of the tree. We rank the
+//! paths in lexicographic order, with pops ordered before pushes. The rank
+//! of a path is the number of paths that come before it, which we find by
+//! counting, for every push, the paths that have a pop at that step
+//! instead. These counts are ballot numbers, which we tabulate once.
+//!
+//! `C_36` is the largest Catalan number that fits in a `u64`, so we
+//! support shapes with at most 36 nodes.
+
+use crate::number::{CartesianTreeNumber, NumberBuilder};
+
+/// The largest number of nodes whose shapes we can rank
+pub const MAX_SHAPE_SIZE: usize = 36;
+
+/// All the shapes of cartesian trees with a given number of nodes. This
+/// ranks and unranks the shapes, and iterates over them in rank order.
+#[derive(Debug, Clone)]
+pub struct Shapes {
+    /// The number of nodes in each shape
+    size: usize,
+
+    /// `completions[r][h]` is the number of ways to finish a path
+    /// that is at height `h` and has `r` steps left to take
+    completions: Vec<Vec<u64>>,
+}
+
+impl Shapes {
+    /// Tabulates the ballot numbers we need to rank
+    /// the shapes of trees with `size` nodes.
+    ///
+    /// Panics if `size` is larger than [`MAX_SHAPE_SIZE`].
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size <= MAX_SHAPE_SIZE,
+            "there are too many shapes of size {} to rank them with a u64",
+            size
+        );
+        let steps = 2 * size;
+        // Heights above the number of steps left can never
+        // come back down, so we leave their counts at 0.
+        let mut completions = vec![vec![0; steps + 2]; steps + 1];
+        completions[0][0] = 1;
+        for r in 1..=steps {
+            for h in 0..=steps - r {
+                let down = if h > 0 { completions[r - 1][h - 1] } else { 0 };
+                completions[r][h] = down + completions[r - 1][h + 1];
+            }
+        }
+        Shapes { size, completions }
+    }
+
+    /// The number of nodes in each shape
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of shapes, which is the Catalan number `C_size`
+    pub fn count(&self) -> u64 {
+        self.completions[2 * self.size][0]
+    }
+
+    /// The number of paths that we can get by taking a pop
+    /// instead of a push at height `height`, with `steps_left`
+    /// steps left to take after this one.
+    fn paths_with_pop(&self, steps_left: usize, height: usize) -> u64 {
+        if height == 0 {
+            0
+        } else {
+            self.completions[steps_left][height - 1]
+        }
+    }
+
+    /// Maps the shape with the given cartesian tree number to its
+    /// dense rank in `0..self.count()`. Returns `None` if the number
+    /// is not the number of a tree with `self.size()` nodes.
+    pub fn rank(&self, number: &CartesianTreeNumber) -> Option<u64> {
+        let steps = 2 * self.size;
+        let (mut rank, mut height, mut pushes) = (0, 0usize, 0);
+        for (step, is_push) in number.actions().enumerate() {
+            if is_push {
+                pushes += 1;
+                if pushes > self.size {
+                    return None;
+                }
+                rank += self.paths_with_pop(steps - step - 1, height);
+                height += 1;
+            } else {
+                height = height.checked_sub(1)?;
+            }
+        }
+        // The pops that empty the stack come before any push, so
+        // appending them to the path does not change its rank
+        if pushes == self.size {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the cartesian tree number of the shape with the given
+    /// rank. Returns `None` if the rank is not below `self.count()`.
+    pub fn unrank(&self, mut rank: u64) -> Option<CartesianTreeNumber> {
+        if rank >= self.count() {
+            return None;
+        }
+        let steps = 2 * self.size;
+        let mut actions = Vec::with_capacity(steps);
+        let mut height = 0;
+        for step in 0..steps {
+            let paths_with_pop = self.paths_with_pop(steps - step - 1, height);
+            if rank < paths_with_pop {
+                actions.push(false);
+                height -= 1;
+            } else {
+                rank -= paths_with_pop;
+                actions.push(true);
+                height += 1;
+            }
+        }
+        // We drop the pops that empty the stack at the end
+        let len = actions
+            .iter()
+            .rposition(|&is_push| is_push)
+            .map_or(0, |last| last + 1);
+        let mut number = NumberBuilder::default();
+        for &is_push in &actions[..len] {
+            number.push_action(is_push);
+        }
+        Some(number.finish())
+    }
+
+    /// Iterates over the cartesian tree numbers of all
+    /// the shapes, in the order of their ranks.
+    pub fn iter(&self) -> ShapesIter<'_> {
+        ShapesIter {
+            shapes: self,
+            next_rank: 0,
+        }
+    }
+}
+
+impl<'s> IntoIterator for &'s Shapes {
+    type Item = CartesianTreeNumber;
+    type IntoIter = ShapesIter<'s>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Yields the cartesian tree numbers of all the shapes of a given size
+#[derive(Debug, Clone)]
+pub struct ShapesIter<'s> {
+    shapes: &'s Shapes,
+    next_rank: u64,
+}
+
+impl<'s> Iterator for ShapesIter<'s> {
+    type Item = CartesianTreeNumber;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let number = self.shapes.unrank(self.next_rank)?;
+        self.next_rank += 1;
+        Some(number)
+    }
+}
+
+/// The `n`th Catalan number, which counts the shapes of cartesian trees
+/// with `n` nodes.
+///
+/// Panics if `n` is larger than [`MAX_SHAPE_SIZE`].
+pub fn catalan(n: usize) -> u64 {
+    Shapes::new(n).count()
+}
@@ -0,0 +1,96 @@
+//! Sparse Table RMQ
+//!
+//! A simple `<O(n lg n), O(1)>` solution to the range min query problem.
+//! For every index `i` and every power of two `2^k`, we precompute the
+//! location of the minimum value in the window of length `2^k` that starts
+//! at `i`. Any range `[i, j)` can then be covered by two, possibly
+//! overlapping, windows of length `2^k` where `k = floor(lg(j - i))`,
+//! so a query needs just two lookups and a comparison.
+
+use crate::tree::TieBreak;
+use bit_parallelism::get_msb_idx_of;
+use std::ops::Range;
+
+/// A range min query structure that precomputes the answers
+/// to all queries whose length is a power of two.
+#[derive(Debug)]
+pub struct SparseTableRmq<'a, T: Ord> {
+    /// The array over which we answer queries
+    underlying: &'a [T],
+
+    /// `sparse_table[k][i]` is the index of the minimum value
+    /// in the window of length `2^k` starting at `i`
+    sparse_table: Vec<Vec<usize>>,
+
+    /// Decides which of several equal minima we report
+    tie_break: TieBreak,
+}
+
+impl<'a, T: Ord> From<&'a [T]> for SparseTableRmq<'a, T> {
+    fn from(underlying: &'a [T]) -> Self {
+        Self::with_tie_break(underlying, TieBreak::default())
+    }
+}
+
+impl<'a, T: Ord> SparseTableRmq<'a, T> {
+    /// Builds the sparse table, resolving duplicate
+    /// minima according to the given policy
+    pub fn with_tie_break(underlying: &'a [T], tie_break: TieBreak) -> Self {
+        let len = underlying.len();
+        let mut sparse_table: Vec<Vec<usize>> = vec![(0..len).collect()];
+        let mut window = 1;
+        while 2 * window <= len {
+            let prev = sparse_table.last().unwrap();
+            let next = (0..=len - 2 * window)
+                .map(|i| min_of(underlying, tie_break, prev[i], prev[i + window]))
+                .collect();
+            sparse_table.push(next);
+            window *= 2;
+        }
+        SparseTableRmq {
+            underlying,
+            sparse_table,
+            tie_break,
+        }
+    }
+
+    /// Returns the index of the minimum value in the given range of the
+    /// underlying array. When the minimum occurs several times, the
+    /// [`TieBreak`] policy decides which occurrence we return. By default,
+    /// this is the rightmost one, just like [`crate::CartesianTree`].
+    ///
+    /// Panics if the range is empty or out of bounds.
+    pub fn query(&self, range: Range<usize>) -> usize {
+        assert!(
+            range.start < range.end && range.end <= self.underlying.len(),
+            "range {:?} is empty or out of bounds for an array of length {}",
+            range,
+            self.underlying.len()
+        );
+        let k = floor_lg(range.end - range.start);
+        let left = self.sparse_table[k][range.start];
+        let right = self.sparse_table[k][range.end - (1 << k)];
+        min_of(self.underlying, self.tie_break, left, right)
+    }
+}
+
+/// Picks the index of the smaller of two values. We assume
+/// that `left <= right`, and break ties according to `tie_break`
+pub(crate) fn min_of<T: Ord>(
+    underlying: &[T],
+    tie_break: TieBreak,
+    left: usize,
+    right: usize,
+) -> usize {
+    if tie_break.earlier_wins(underlying[left].cmp(&underlying[right])) {
+        left
+    } else {
+        right
+    }
+}
+
+/// Computes `floor(lg(x))` for a positive `x`. This is simply
+/// the index of the most significant bit of `x`.
+pub(crate) fn floor_lg(x: usize) -> usize {
+    get_msb_idx_of(x as u64) as usize
+}
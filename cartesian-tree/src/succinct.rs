@@ -0,0 +1,458 @@
+//! Succinct RMQ
+//!
+//! A range min query structure that answers queries without keeping the
+//! underlying array around, in `2n + O(n / lg n)` bits. This is the approach of
+//! [Fischer](https://doi.org/10.1007/978-3-642-13509-5_16), in the balanced
+//! parentheses form of [Ferrada and Navarro](https://arxiv.org/abs/1601.01044).
+//!
+//! We write down the actions of the stack based construction of the
+//! cartesian tree: an opening parenthesis for every push, and a closing
+//! parenthesis for every pop. Once we run out of values, we pop whatever is
+//! left on the stack. We also wrap the whole sequence in one more pair of
+//! parentheses. Right before the push of the value at index `i`, the depth
+//! of the parentheses, which we call the excess, is the number of values
+//! that stay on the stack below `i`. The minimum of `[i, j]` is the lowest
+//! value of that range still on the stack once we push `j`. Before we push
+//! it, the excess dips down to the number of values below it, and the
+//! excess never dips that low again until we push `j`. So the minimum is
+//! the value whose push comes right after the last position at which the
+//! excess is smallest, between the push of `i` and that of `j`.
+//!
+//! To find that position quickly, we cut the parentheses into blocks of
+//! `lg n` words, and record the excess at the start of each block. A binary
+//! tree over the blocks holds the smallest excess in every block and every
+//! run of blocks under a node. This is the min half of the range min-max
+//! tree of [Navarro and Sadakane](https://doi.org/10.1145/2601073). Each
+//! block keeps its excess and at most four nodes of the tree, so the blocks
+//! add at most `5 / lg n` bits per parenthesis.
+//!
+//! A query finds the parentheses of the ends of its range with a binary
+//! search over the blocks, reads two partial blocks a byte at a time, and
+//! looks at `O(lg n)` nodes of the tree, so it takes `O(lg n)` time. Getting
+//! down to constant time takes further directories over the blocks, which
+//! we leave out.
+
+use crate::index::NodeIndex;
+use crate::number::ShapeStack;
+use crate::sparse_table::floor_lg;
+use crate::tree::{CartesianTree, TieBreak};
+use std::iter;
+use std::ops::Range;
+
+const WORD_BITS: usize = 64;
+
+/// How a byte of parentheses changes the excess. Bits are read from the
+/// least significant one, and a set bit is an opening parenthesis.
+#[derive(Debug, Clone, Copy)]
+struct ByteSummary {
+    /// The change in excess over the whole byte
+    excess: i8,
+
+    /// The smallest change in excess after any bit of the
+    /// byte, and the last bit after which we attain it
+    min: i8,
+    last_min: u8,
+}
+
+const BYTE_SUMMARIES: [ByteSummary; 256] = summarize_bytes();
+
+const fn summarize_bytes() -> [ByteSummary; 256] {
+    let mut summaries = [ByteSummary {
+        excess: 0,
+        min: 0,
+        last_min: 0,
+    }; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let (mut excess, mut min, mut last_min) = (0i8, i8::MAX, 0u8);
+        let mut bit = 0;
+        while bit < 8 {
+            excess += if byte >> bit & 1 == 1 { 1 } else { -1 };
+            if excess <= min {
+                min = excess;
+                last_min = bit as u8;
+            }
+            bit += 1;
+        }
+        summaries[byte] = ByteSummary {
+            excess,
+            min,
+            last_min,
+        };
+        byte += 1;
+    }
+    summaries
+}
+
+/// Answers range min queries in `2n + O(n / lg n)` bits, and
+/// `O(lg n)` time, without access to the underlying array.
+///
+/// ```rust
+/// use cartesian_tree::SuccinctRmq;
+///
+/// let v = vec![93, 84, 33, 64, 62, 83, 63];
+/// let rmq = SuccinctRmq::from(v.as_slice());
+/// drop(v);
+/// assert_eq!(rmq.query(3..7), 4);
+/// ```
+///
+/// With the `serde` feature, we only save the parentheses. Loading them
+/// checks that they are balanced, and builds the blocks over them again.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SuccinctRmq {
+    /// The parentheses, one bit each, with
+    /// a set bit for an opening parenthesis
+    bits: Vec<u64>,
+    bit_len: usize,
+
+    /// The number of parentheses in a block, which is a
+    /// multiple of the word size that grows with `lg n`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    block_bits: usize,
+
+    /// The excess right before the first parenthesis of every block
+    #[cfg_attr(feature = "serde", serde(skip))]
+    block_excess: Vec<usize>,
+
+    /// A complete binary tree over the blocks, laid out like a binary
+    /// heap from index 1. Every node holds the smallest excess after any
+    /// parenthesis in the blocks below it. Leaves past the last block
+    /// hold `usize::MAX`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    min_tree: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    leaves: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SuccinctRmq {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Parentheses {
+            bits: Vec<u64>,
+            bit_len: usize,
+        }
+
+        let Parentheses { bits, bit_len } = Parentheses::deserialize(deserializer)?;
+        let saved = SuccinctRmq {
+            bits,
+            bit_len,
+            block_bits: 0,
+            block_excess: Vec::new(),
+            min_tree: Vec::new(),
+            leaves: 0,
+        };
+        if !saved.is_balanced() {
+            return Err(serde::de::Error::custom(
+                "the parentheses are not balanced inside an outermost pair",
+            ));
+        }
+        Ok(Self::from_actions(
+            (1..bit_len).map(|position| saved.bit(position)),
+        ))
+    }
+}
+
+impl<'a, T: Ord> From<&'a [T]> for SuccinctRmq {
+    fn from(underlying: &'a [T]) -> Self {
+        Self::with_tie_break(underlying, TieBreak::default())
+    }
+}
+
+/// Encodes the shape of the tree. The structure reports the same
+/// minima as [`CartesianTree::range_min`] does on the tree.
+impl<'a, 't, T, I: NodeIndex, V: AsRef<[T]>> From<&'t CartesianTree<'a, T, I, V>> for SuccinctRmq {
+    fn from(tree: &'t CartesianTree<'a, T, I, V>) -> Self {
+        Self::from_actions(tree.signature().actions())
+    }
+}
+
+impl SuccinctRmq {
+    /// Builds the structure, resolving duplicate minima according to
+    /// the given policy. This does not build the tree itself, and only
+    /// keeps the right spine of the tree around while it runs.
+    pub fn with_tie_break<T: Ord>(underlying: &[T], tie_break: TieBreak) -> Self {
+        let mut spine = ShapeStack::new(tie_break);
+        Self::from_actions(underlying.iter().flat_map(|value| {
+            let pops = spine.push(value);
+            iter::repeat(false).take(pops).chain(iter::once(true))
+        }))
+    }
+
+    /// Writes down the parentheses for the given push and pop actions,
+    /// and builds the blocks and the tree over them
+    fn from_actions<I: Iterator<Item = bool>>(actions: I) -> Self {
+        let mut rmq = SuccinctRmq {
+            bits: Vec::new(),
+            bit_len: 0,
+            block_bits: 0,
+            block_excess: Vec::new(),
+            min_tree: Vec::new(),
+            leaves: 0,
+        };
+        rmq.push_bit(true);
+        let mut height = 1;
+        for is_push in actions {
+            rmq.push_bit(is_push);
+            height = if is_push { height + 1 } else { height - 1 };
+        }
+        for _ in 0..height {
+            rmq.push_bit(false);
+        }
+
+        rmq.block_bits = WORD_BITS * floor_lg(rmq.bit_len);
+        let blocks = (rmq.bit_len + rmq.block_bits - 1) / rmq.block_bits;
+        rmq.leaves = blocks.next_power_of_two();
+        rmq.min_tree = vec![usize::MAX; 2 * rmq.leaves];
+        let mut excess = 0;
+        for block in 0..blocks {
+            let start = block * rmq.block_bits;
+            let end = (start + rmq.block_bits).min(rmq.bit_len) - 1;
+            rmq.block_excess.push(excess);
+            let (min, _) = rmq.scan(start, end, excess as isize);
+            rmq.min_tree[rmq.leaves + block] = min as usize;
+            excess = rmq.excess_after(end);
+        }
+        for node in (1..rmq.leaves).rev() {
+            rmq.min_tree[node] = rmq.min_tree[2 * node].min(rmq.min_tree[2 * node + 1]);
+        }
+        rmq
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        if self.bit_len % WORD_BITS == 0 {
+            self.bits.push(0);
+        }
+        if bit {
+            self.bits[self.bit_len / WORD_BITS] |= 1 << (self.bit_len % WORD_BITS);
+        }
+        self.bit_len += 1;
+    }
+
+    fn bit(&self, position: usize) -> bool {
+        self.bits[position / WORD_BITS] >> (position % WORD_BITS) & 1 == 1
+    }
+
+    /// Checks that the parentheses are balanced, and that the first one
+    /// only closes at the very end, like those we write down when building
+    #[cfg(feature = "serde")]
+    fn is_balanced(&self) -> bool {
+        if self.bit_len < 2 || self.bits.len() != (self.bit_len + WORD_BITS - 1) / WORD_BITS {
+            return false;
+        }
+        let mut excess = 0usize;
+        for position in 0..self.bit_len {
+            if self.bit(position) {
+                excess += 1;
+            } else if excess == 0 {
+                return false;
+            } else {
+                excess -= 1;
+            }
+            if excess == 0 && position + 1 < self.bit_len {
+                return false;
+            }
+        }
+        excess == 0
+    }
+
+    /// The number of values in the underlying array
+    pub fn len(&self) -> usize {
+        self.bit_len / 2 - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the index of the minimum value in the given range of the
+    /// underlying array. When the minimum occurs several times, the
+    /// [`TieBreak`] policy decides which occurrence we return. By default,
+    /// this is the rightmost one, just like [`crate::CartesianTree`].
+    ///
+    /// Panics if the range is empty or out of bounds.
+    pub fn query(&self, range: Range<usize>) -> usize {
+        assert!(
+            range.start < range.end && range.end <= self.len(),
+            "range {:?} is empty or out of bounds for an array of length {}",
+            range,
+            self.len()
+        );
+        // The outermost parenthesis comes before that of every value,
+        // so the value at index `i` has the opening parenthesis of rank
+        // `i + 1`, and there is always a position before it
+        let from = self.select_open(range.start + 1) - 1;
+        let to = self.select_open(range.end);
+        let (min, position) = self.last_min(from, to);
+
+        // The parenthesis after `position` opens the minimum. Up to and
+        // including it, `(position + 2 + min + 1) / 2` parentheses are
+        // opening ones, the first of which is the outermost one.
+        (position + 2 + min + 1) / 2 - 2
+    }
+
+    /// The excess right after the parenthesis at `position`
+    fn excess_after(&self, position: usize) -> usize {
+        let block = position / self.block_bits;
+        let mut excess = self.block_excess[block] as isize;
+        let mut word = block * self.block_bits / WORD_BITS;
+        while (word + 1) * WORD_BITS <= position {
+            excess += 2 * self.bits[word].count_ones() as isize - WORD_BITS as isize;
+            word += 1;
+        }
+        let bits = position % WORD_BITS + 1;
+        let mask = if bits == WORD_BITS {
+            u64::MAX
+        } else {
+            (1 << bits) - 1
+        };
+        excess += 2 * (self.bits[word] & mask).count_ones() as isize - bits as isize;
+        excess as usize
+    }
+
+    /// The position of the opening parenthesis with the given rank,
+    /// counting from 0. We find its block with a binary search, since
+    /// the number of opening parentheses before a block follows from
+    /// the excess at its start.
+    fn select_open(&self, rank: usize) -> usize {
+        let opens_before = |block: usize| (block * self.block_bits + self.block_excess[block]) / 2;
+        let (mut lo, mut hi) = (0, self.block_excess.len());
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if opens_before(mid) <= rank {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let mut remaining = rank - opens_before(lo);
+        let mut word = lo * self.block_bits / WORD_BITS;
+        loop {
+            let mut bits = self.bits[word];
+            let count = bits.count_ones() as usize;
+            if remaining < count {
+                for _ in 0..remaining {
+                    bits &= bits - 1;
+                }
+                return word * WORD_BITS + bits.trailing_zeros() as usize;
+            }
+            remaining -= count;
+            word += 1;
+        }
+    }
+
+    /// Finds the smallest excess after any position in `[from, to]`,
+    /// along with the last position after which we attain it
+    fn last_min(&self, from: usize, to: usize) -> (usize, usize) {
+        let (first_block, last_block) = (from / self.block_bits, to / self.block_bits);
+        let excess_before = |position: usize| {
+            if position == 0 {
+                0
+            } else {
+                self.excess_after(position - 1) as isize
+            }
+        };
+        if first_block == last_block {
+            let (min, position) = self.scan(from, to, excess_before(from));
+            return (min as usize, position);
+        }
+        let block_end = (first_block + 1) * self.block_bits - 1;
+        let (mut min, mut position) = self.scan(from, block_end, excess_before(from));
+        if first_block + 1 < last_block {
+            let (node_min, node) =
+                self.min_node(1, 0, self.leaves - 1, first_block + 1, last_block - 1);
+            if node_min as isize <= min {
+                let block = self.last_min_leaf(node) - self.leaves;
+                let start = block * self.block_bits;
+                let block_min = self.scan(
+                    start,
+                    start + self.block_bits - 1,
+                    self.block_excess[block] as isize,
+                );
+                min = block_min.0;
+                position = block_min.1;
+            }
+        }
+        let block_start = last_block * self.block_bits;
+        let last = self.scan(block_start, to, self.block_excess[last_block] as isize);
+        if last.0 <= min {
+            return (last.0 as usize, last.1);
+        }
+        (min as usize, position)
+    }
+
+    /// Among the nodes that cover the blocks in `[first, last]`, finds the
+    /// last one that holds the smallest excess. `node` covers the blocks in
+    /// `[node_first, node_last]`.
+    fn min_node(
+        &self,
+        node: usize,
+        node_first: usize,
+        node_last: usize,
+        first: usize,
+        last: usize,
+    ) -> (usize, usize) {
+        if first <= node_first && node_last <= last {
+            return (self.min_tree[node], node);
+        }
+        let mid = node_first + (node_last - node_first) / 2;
+        if last <= mid {
+            return self.min_node(2 * node, node_first, mid, first, last);
+        }
+        if first > mid {
+            return self.min_node(2 * node + 1, mid + 1, node_last, first, last);
+        }
+        let left = self.min_node(2 * node, node_first, mid, first, last);
+        let right = self.min_node(2 * node + 1, mid + 1, node_last, first, last);
+        if right.0 <= left.0 {
+            right
+        } else {
+            left
+        }
+    }
+
+    /// Walks down from `node` to the last leaf that holds its minimum
+    fn last_min_leaf(&self, mut node: usize) -> usize {
+        while node < self.leaves {
+            node = if self.min_tree[2 * node + 1] <= self.min_tree[2 * node] {
+                2 * node + 1
+            } else {
+                2 * node
+            };
+        }
+        node
+    }
+
+    /// Finds the smallest excess after any position in `[from, to]`, along
+    /// with the last position after which we attain it, by reading the
+    /// parentheses one at a time, or a byte at a time where we can. The
+    /// excess right before `from` is `excess`.
+    fn scan(&self, from: usize, to: usize, mut excess: isize) -> (isize, usize) {
+        let (mut min, mut min_position) = (isize::MAX, from);
+        let mut position = from;
+        while position <= to {
+            if position % 8 == 0 && position + 7 <= to {
+                let byte = (self.bits[position / WORD_BITS] >> (position % WORD_BITS)) as u8;
+                let summary = BYTE_SUMMARIES[byte as usize];
+                if excess + summary.min as isize <= min {
+                    min = excess + summary.min as isize;
+                    min_position = position + summary.last_min as usize;
+                }
+                excess += summary.excess as isize;
+                position += 8;
+            } else {
+                excess += if self.bit(position) { 1 } else { -1 };
+                if excess <= min {
+                    min = excess;
+                    min_position = position;
+                }
+                position += 1;
+            }
+        }
+        (min, min_position)
+    }
+}
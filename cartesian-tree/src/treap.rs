@@ -0,0 +1,314 @@
+//! Treap
+//!
+//! A treap is a cartesian tree over `(key, priority)` pairs: it is a binary
+//! search tree on the keys, and a min heap on the priorities. When the
+//! priorities are random, the shape of the tree is that of a binary search
+//! tree into which the keys were inserted in random order, so the tree has
+//! an expected depth of `O(lg n)` no matter what order the keys come in.
+//!
+//! Unlike a [`crate::CartesianTree`], a treap can change after it is built.
+//! Every operation is built out of two primitives: `split`, which cuts a
+//! treap in two around a key, and `merge`, which joins two treaps whose keys
+//! do not overlap. Both walk a single path down the tree, and thus take
+//! expected `O(lg n)` time.
+//!
+//! When the keys are already sorted, we can instead build the treap in
+//! linear time. We draw a priority for each key, and build the
+//! [`crate::CartesianTree`] of the priorities, whose shape the treap takes on.
+
+use crate::treap_node::{self, link_by_priority, merge, size, Entry, Priorities, TreapNode};
+use std::cmp::Ordering;
+use std::mem;
+
+type Link<K, V> = treap_node::Link<KeyValue<K, V>>;
+
+#[derive(Debug, Clone)]
+struct KeyValue<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K, V> Entry for KeyValue<K, V> {}
+
+fn new_node<K, V>(key: K, value: V, priority: u64) -> Box<TreapNode<KeyValue<K, V>>> {
+    TreapNode::new(KeyValue { key, value }, priority)
+}
+
+/// A sorted map from keys to values, kept balanced with random priorities.
+#[derive(Debug, Clone)]
+pub struct Treap<K, V> {
+    root: Link<K, V>,
+    priorities: Priorities,
+}
+
+impl<K: Ord, V> Default for Treap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> Treap<K, V> {
+    /// An empty treap with randomly seeded priorities
+    pub fn new() -> Self {
+        Treap {
+            root: None,
+            priorities: Priorities::new(),
+        }
+    }
+
+    /// An empty treap whose priorities are drawn from a generator with the
+    /// given seed. Two treaps with the same seed that see the same
+    /// operations have the same shape.
+    pub fn with_seed(seed: u64) -> Self {
+        Treap {
+            root: None,
+            priorities: Priorities::with_seed(seed),
+        }
+    }
+
+    /// Builds a treap out of entries whose keys are sorted and
+    /// distinct in linear time, with randomly seeded priorities.
+    ///
+    /// Panics if the keys are not sorted and distinct.
+    pub fn from_sorted<I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_sorted_with_priorities(entries, Priorities::new())
+    }
+
+    /// Builds a treap out of entries whose keys are sorted and distinct in
+    /// linear time, with priorities drawn from a generator with the given
+    /// seed. Building a treap twice from the same entries and seed gives
+    /// the same shape.
+    ///
+    /// Panics if the keys are not sorted and distinct.
+    pub fn from_sorted_with_seed<I>(entries: I, seed: u64) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_sorted_with_priorities(entries, Priorities::with_seed(seed))
+    }
+
+    fn from_sorted_with_priorities<I>(entries: I, mut priorities: Priorities) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut nodes: Vec<Box<TreapNode<KeyValue<K, V>>>> = Vec::new();
+        for (key, value) in entries {
+            if let Some(last) = nodes.last() {
+                assert!(last.entry.key < key, "keys must be sorted and distinct");
+            }
+            nodes.push(new_node(key, value, priorities.next()));
+        }
+        Treap {
+            root: link_by_priority(nodes),
+            priorities,
+        }
+    }
+
+    /// The number of entries in the treap
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// The entry with the smallest key, if any
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(left) = node.left.as_ref() {
+            node = left;
+        }
+        Some((&node.entry.key, &node.entry.value))
+    }
+
+    /// The entry with the largest key, if any
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = node.right.as_ref() {
+            node = right;
+        }
+        Some((&node.entry.key, &node.entry.value))
+    }
+
+    /// The value stored under the given key, if any
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut cur = self.root.as_ref();
+        while let Some(node) = cur {
+            cur = match key.cmp(&node.entry.key) {
+                Ordering::Less => node.left.as_ref(),
+                Ordering::Greater => node.right.as_ref(),
+                Ordering::Equal => return Some(&node.entry.value),
+            };
+        }
+        None
+    }
+
+    /// A mutable reference to the value stored under the given key, if any
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut cur = self.root.as_mut();
+        while let Some(node) = cur {
+            cur = match key.cmp(&node.entry.key) {
+                Ordering::Less => node.left.as_mut(),
+                Ordering::Greater => node.right.as_mut(),
+                Ordering::Equal => return Some(&mut node.entry.value),
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Stores the value under the given key. If the key was already
+    /// present, we replace its value, and return the old one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(mem::replace(old, value));
+        }
+        let node = new_node(key, value, self.priorities.next());
+        let (smaller, larger) = split(self.root.take(), &node.entry.key, false);
+        self.root = merge(merge(smaller, Some(node)), larger);
+        None
+    }
+
+    /// Removes the given key, and returns its value if it was present
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (smaller, rest) = split(self.root.take(), key, false);
+        let (equal, larger) = split(rest, key, true);
+        self.root = merge(smaller, larger);
+        equal.map(|node| node.entry.value)
+    }
+
+    /// Splits the treap in two. Afterwards, `self` holds
+    /// the keys that are smaller than `key`, and we return a
+    /// treap that holds the rest.
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let (smaller, larger) = split(self.root.take(), key, false);
+        self.root = smaller;
+        Treap {
+            root: larger,
+            priorities: Priorities::with_seed(self.priorities.next()),
+        }
+    }
+
+    /// Moves all the entries of `other` into `self`.
+    ///
+    /// Panics if some key of `other` is not larger than every key of `self`.
+    pub fn merge(&mut self, other: Self) {
+        if let (Some(last), Some(first)) = (self.last_key_value(), other.first_key_value()) {
+            assert!(
+                last.0 < first.0,
+                "the keys of the merged treap must come after those of this one"
+            );
+        }
+        self.root = merge(self.root.take(), other.root);
+    }
+
+    /// Iterates over the entries of the treap in the order of their keys
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: treap_node::Nodes::new(&self.root),
+        }
+    }
+}
+
+/// Cuts the treap rooted at `link` into the nodes whose keys are smaller
+/// than `key`, and the rest. When `key_goes_left` is set, a node with a key
+/// equal to `key` goes with the smaller keys instead.
+fn split<K: Ord, V>(link: Link<K, V>, key: &K, key_goes_left: bool) -> (Link<K, V>, Link<K, V>) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            let goes_left = match node.entry.key.cmp(key) {
+                Ordering::Less => true,
+                Ordering::Equal => key_goes_left,
+                Ordering::Greater => false,
+            };
+            if goes_left {
+                let (smaller, larger) = split(node.right.take(), key, key_goes_left);
+                node.right = smaller;
+                node.update();
+                (Some(node), larger)
+            } else {
+                let (smaller, larger) = split(node.left.take(), key, key_goes_left);
+                node.left = larger;
+                node.update();
+                (smaller, Some(node))
+            }
+        }
+    }
+}
+
+/// Walks a treap in the order of its keys
+pub struct Iter<'t, K, V> {
+    nodes: treap_node::Nodes<'t, KeyValue<K, V>>,
+}
+
+impl<'t, K, V> Iterator for Iter<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.next()?;
+        Some((&node.entry.key, &node.entry.value))
+    }
+}
+
+impl<'t, K: Ord, V> IntoIterator for &'t Treap<K, V> {
+    type Item = (&'t K, &'t V);
+    type IntoIter = Iter<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[test]
+fn test_treap_is_a_heap() {
+    fn check<K: Ord, V>(link: &Link<K, V>) -> usize {
+        let mut count = 0;
+        let mut stack: Vec<&TreapNode<KeyValue<K, V>>> = link.iter().map(|node| &**node).collect();
+        while let Some(node) = stack.pop() {
+            for child in [&node.left, &node.right].into_iter().flatten() {
+                assert!(node.priority <= child.priority);
+                stack.push(child);
+            }
+            assert_eq!(node.size, 1 + size(&node.left) + size(&node.right));
+            count += 1;
+        }
+        count
+    }
+
+    let mut treap = Treap::from_sorted_with_seed((0..1000).map(|key| (key, key)), 11);
+    assert_eq!(check(&treap.root), 1000);
+
+    // The same seed gives the same shape
+    let shape = |treap: &Treap<i32, i32>| {
+        let mut stack: Vec<&TreapNode<KeyValue<i32, i32>>> =
+            treap.root.iter().map(|n| &**n).collect();
+        let mut pre_order = Vec::new();
+        while let Some(node) = stack.pop() {
+            pre_order.push((node.entry.key, node.size));
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
+        }
+        pre_order
+    };
+    let again = Treap::from_sorted_with_seed((0..1000).map(|key| (key, key)), 11);
+    assert_eq!(shape(&again), shape(&treap));
+    for key in (0..1000).step_by(3) {
+        treap.remove(&key);
+    }
+    for key in 1000..1500 {
+        treap.insert(key, key);
+    }
+    let larger = treap.split_off(&700);
+    assert_eq!(check(&treap.root), treap.len());
+    assert_eq!(check(&larger.root), larger.len());
+    treap.merge(larger);
+    assert_eq!(check(&treap.root), treap.len());
+}
@@ -0,0 +1,161 @@
+//! Treap Nodes
+//!
+//! The nodes that [`crate::Treap`] and [`crate::ImplicitTreap`] are made of,
+//! along with everything the two do the same way whatever their nodes hold:
+//! drawing priorities, building a treap out of nodes that are already in
+//! order, merging two treaps, and walking a treap in order.
+
+use crate::node::NodeRef;
+use crate::tree::{CartesianTree, TieBreak};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+pub(crate) type Link<E> = Option<Box<TreapNode<E>>>;
+
+#[derive(Debug, Clone)]
+pub(crate) struct TreapNode<E> {
+    pub(crate) entry: E,
+
+    /// Every node has a smaller priority than its descendants
+    pub(crate) priority: u64,
+    pub(crate) left: Link<E>,
+    pub(crate) right: Link<E>,
+
+    /// The number of nodes in the subtree rooted at this node
+    pub(crate) size: usize,
+}
+
+/// What a treap keeps in each of its nodes
+pub(crate) trait Entry: Sized {
+    /// Recomputes what the entry of `node` knows about the subtree of the
+    /// node, after its children change. The size is already up to date.
+    fn update(_node: &mut TreapNode<Self>) {}
+}
+
+impl<E: Entry> TreapNode<E> {
+    pub(crate) fn new(entry: E, priority: u64) -> Box<Self> {
+        let mut node = Box::new(TreapNode {
+            entry,
+            priority,
+            left: None,
+            right: None,
+            size: 1,
+        });
+        node.update();
+        node
+    }
+
+    /// Recomputes what this node knows about its subtree after its children change
+    pub(crate) fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+        E::update(self);
+    }
+}
+
+pub(crate) fn size<E>(link: &Link<E>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// A xorshift generator for node priorities
+#[derive(Debug, Clone)]
+pub(crate) struct Priorities {
+    state: u64,
+}
+
+impl Priorities {
+    /// A generator seeded from the standard library's source of randomness
+    pub(crate) fn new() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0x2545_f491_4f6c_dd1d);
+        Self::with_seed(hasher.finish())
+    }
+
+    /// A generator that always draws the same priorities for the same seed
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        // Xorshift never leaves the all zeros state
+        Priorities { state: seed | 1 }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+/// Links nodes that come in order into a treap, in linear time. The treap
+/// has the shape of the [`CartesianTree`] of the priorities, in which the
+/// earlier of two equal priorities stays on top, as in [`merge`]. We put
+/// the nodes together from the bottom up, so every node we link has its
+/// subtree complete.
+pub(crate) fn link_by_priority<E: Entry>(nodes: Vec<Box<TreapNode<E>>>) -> Link<E> {
+    let priorities: Vec<u64> = nodes.iter().map(|node| node.priority).collect();
+    let shape = CartesianTree::from_slice_with(&priorities, TieBreak::Leftmost, u64::cmp);
+    let mut nodes: Vec<Link<E>> = nodes.into_iter().map(Some).collect();
+    for (idx, _) in shape.iter_post_order() {
+        let linked = shape.node(idx).unwrap();
+        let mut child = |child: Option<NodeRef<'_, u64>>| {
+            child.and_then(|child| nodes[child.array_index()].take())
+        };
+        let (left, right) = (child(linked.left()), child(linked.right()));
+        let node = nodes[idx].as_mut().unwrap();
+        node.left = left;
+        node.right = right;
+        node.update();
+    }
+    shape
+        .root()
+        .and_then(|root| nodes[root.array_index()].take())
+}
+
+/// Joins two treaps, putting all the nodes of `left` before those of `right`
+pub(crate) fn merge<E: Entry>(left: Link<E>, right: Link<E>) -> Link<E> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut l), Some(mut r)) => {
+            if l.priority <= r.priority {
+                l.right = merge(l.right.take(), Some(r));
+                l.update();
+                Some(l)
+            } else {
+                r.left = merge(Some(l), r.left.take());
+                r.update();
+                Some(r)
+            }
+        }
+    }
+}
+
+/// Walks the nodes of a treap in order
+#[derive(Debug)]
+pub(crate) struct Nodes<'t, E> {
+    /// The nodes whose left subtrees we are currently visiting
+    stack: Vec<&'t TreapNode<E>>,
+}
+
+impl<'t, E> Nodes<'t, E> {
+    pub(crate) fn new(root: &'t Link<E>) -> Self {
+        let mut nodes = Nodes { stack: Vec::new() };
+        nodes.push_left_spine(root.as_deref());
+        nodes
+    }
+
+    fn push_left_spine(&mut self, mut cur: Option<&'t TreapNode<E>>) {
+        while let Some(node) = cur {
+            self.stack.push(node);
+            cur = node.left.as_deref();
+        }
+    }
+}
+
+impl<'t, E> Iterator for Nodes<'t, E> {
+    type Item = &'t TreapNode<E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.right.as_deref());
+        Some(node)
+    }
+}
@@ -1,65 +1,484 @@
 //! Cartesian Tree
 //!
 
-type Nodes<'a, T> = Vec<CartesianTreeNode<'a, T>>;
-type Stack = Vec<CartesianNodeIdx>;
-type Actions = Vec<CartesianTreeAction>;
+use crate::index::{check_width, IndexWidthError, NodeIndex};
+use crate::iter::{InOrder, LevelOrder, MinSpans, PostOrder, PreOrder};
+use crate::lca::LowestCommonAncestor;
+use crate::node::NodeRef;
+use crate::number::{CartesianTreeNumber, NumberBuilder, ShapeStack};
+use once_cell::sync::OnceCell;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::Range;
 
-/// An index into a collection of cartesian tree nodes
+pub(crate) type Nodes<I = usize> = Vec<CartesianTreeNode<I>>;
+pub(crate) type Stack<I = usize> = Vec<CartesianNodeIdx<I>>;
+
+/// An index into a collection of cartesian tree nodes. We store
+/// it as a link, so that an `Option` of it takes no extra room.
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
-struct CartesianNodeIdx(usize);
+pub(crate) struct CartesianNodeIdx<I: NodeIndex = usize>(I::Link);
+
+impl<I: NodeIndex> CartesianNodeIdx<I> {
+    pub(crate) fn new(idx: usize) -> Self {
+        CartesianNodeIdx(I::link(idx))
+    }
 
-impl<'a, T: Ord> std::ops::Index<CartesianNodeIdx> for Vec<CartesianTreeNode<'a, T>> {
-    type Output = CartesianTreeNode<'a, T>;
-    fn index(&self, index: CartesianNodeIdx) -> &Self::Output {
-        &self[index.0]
+    pub(crate) fn get(&self) -> usize {
+        I::unlink(self.0)
     }
 }
 
-impl<'a, T: Ord> std::ops::IndexMut<CartesianNodeIdx> for Vec<CartesianTreeNode<'a, T>> {
-    fn index_mut(&mut self, index: CartesianNodeIdx) -> &mut Self::Output {
-        &mut self[index.0]
+/// We save the index itself rather than the link, so that saved
+/// trees do not depend on how we lay out links in memory
+#[cfg(feature = "serde")]
+impl<I: NodeIndex> serde::Serialize for CartesianNodeIdx<I> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        I::from_usize(self.get()).serialize(serializer)
     }
 }
 
-#[derive(Debug)]
-struct CartesianTreeNode<'a, T: Ord> {
-    /// A reference to the array value that this node represents
-    value: &'a T,
+#[cfg(feature = "serde")]
+impl<'de, I: NodeIndex> serde::Deserialize<'de> for CartesianNodeIdx<I> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let idx = I::deserialize(deserializer)?.to_usize();
+        if idx >= I::max_len() {
+            return Err(serde::de::Error::custom(format_args!(
+                "node index {} is out of range",
+                idx
+            )));
+        }
+        Ok(CartesianNodeIdx::new(idx))
+    }
+}
+
+impl<I: NodeIndex> std::ops::Index<CartesianNodeIdx<I>> for Vec<CartesianTreeNode<I>> {
+    type Output = CartesianTreeNode<I>;
+    fn index(&self, index: CartesianNodeIdx<I>) -> &Self::Output {
+        &self[index.get()]
+    }
+}
 
+impl<I: NodeIndex> std::ops::IndexMut<CartesianNodeIdx<I>> for Vec<CartesianTreeNode<I>> {
+    fn index_mut(&mut self, index: CartesianNodeIdx<I>) -> &mut Self::Output {
+        &mut self[index.get()]
+    }
+}
+
+/// A single node in the tree. The node at index `i` of the arena
+/// represents the value at index `i` of the underlying array, so
+/// we only need to keep track of how the nodes are linked.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound = ""))]
+pub(crate) struct CartesianTreeNode<I: NodeIndex = usize> {
     /// The locations of the children and parent of this node.
-    left_child_idx: Option<CartesianNodeIdx>,
-    right_child_idx: Option<CartesianNodeIdx>,
+    pub(crate) left_child_idx: Option<CartesianNodeIdx<I>>,
+    pub(crate) right_child_idx: Option<CartesianNodeIdx<I>>,
+    pub(crate) parent_idx: Option<CartesianNodeIdx<I>>,
+
+    /// The subtree rooted at this node spans the range
+    /// `[span_start, span_end)` of the underlying array.
+    /// We do not save these, since they follow from the links.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span_start: I,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span_end: I,
 }
 
-impl<'a, T: Ord> From<&'a T> for CartesianTreeNode<'a, T> {
-    fn from(value: &'a T) -> Self {
+impl<I: NodeIndex> CartesianTreeNode<I> {
+    /// The range of the underlying array that the subtree rooted at this node spans
+    pub(crate) fn span(&self) -> Range<usize> {
+        self.span_start.to_usize()..self.span_end.to_usize()
+    }
+
+    pub(crate) fn set_span_start(&mut self, start: usize) {
+        self.span_start = I::from_usize(start);
+    }
+
+    pub(crate) fn set_span_end(&mut self, end: usize) {
+        self.span_end = I::from_usize(end);
+    }
+
+    /// Moves this node `offset` places to the right, along with
+    /// every node it links to, as when we put other nodes before it
+    pub(crate) fn shifted(self, offset: usize) -> Self {
+        let shift = |idx: Option<CartesianNodeIdx<I>>| {
+            idx.map(|idx| CartesianNodeIdx::new(offset + idx.get()))
+        };
+        let span = self.span();
         CartesianTreeNode {
-            value,
-            left_child_idx: None,
-            right_child_idx: None,
+            left_child_idx: shift(self.left_child_idx),
+            right_child_idx: shift(self.right_child_idx),
+            parent_idx: shift(self.parent_idx),
+            span_start: I::from_usize(offset + span.start),
+            span_end: I::from_usize(offset + span.end),
         }
     }
 }
 
-/// When constructing a cartesian tree, we either
-/// push a node to or pop a node from a stack.
-/// We keep track of these actions because we can
-/// use them to generate the cartesian tree number.
-#[derive(Debug, Eq, PartialEq)]
-enum CartesianTreeAction {
-    Push,
-    Pop,
+/// Decides which of several equal values is considered
+/// to be the minimum of a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// The leftmost occurrence of the minimum wins. Among equal
+    /// values, the earlier one becomes the ancestor of the later ones.
+    Leftmost,
+
+    /// The rightmost occurrence of the minimum wins. Among equal
+    /// values, the later one becomes the ancestor of the earlier ones.
+    #[default]
+    Rightmost,
+}
+
+impl TieBreak {
+    /// Given the result of comparing an earlier value against a later
+    /// one, decides whether the earlier value is the smaller of the two.
+    /// During construction, this tells us whether the node atop the
+    /// stack stays an ancestor of the node we are adding.
+    pub(crate) fn earlier_wins(self, earlier_vs_later: Ordering) -> bool {
+        match self {
+            TieBreak::Leftmost => earlier_vs_later != Ordering::Greater,
+            TieBreak::Rightmost => earlier_vs_later == Ordering::Less,
+        }
+    }
 }
 
 /// A cartesian tree is a heap ordered binary tree
 /// derived from some underlying array. An in-order
 /// traversal of the tree yields the underlying array.
+///
+/// The nodes of the tree refer to each other with indexes of type `I`.
+/// Trees with narrower indexes take less memory, but can only hold as
+/// many nodes as the index type can address. Such trees are built with
+/// checked constructors like [`CartesianTree::try_from_slice_with`].
+///
+/// The tree keeps its values in a `V`. By default, that is the slice
+/// the tree was built from, but see [`OwnedCartesianTree`].
+#[derive(Debug)]
+pub struct CartesianTree<'a, T, I: NodeIndex = usize, V = &'a [T]> {
+    values: V,
+    pub(crate) links: Links<I>,
+
+    /// We only ever reach the values, and the array
+    /// they may be borrowed from, through `V`
+    values_type: PhantomData<fn() -> T>,
+    array_lifetime: PhantomData<&'a ()>,
+}
+
+/// A cartesian tree that owns its underlying array. Such a tree
+/// is not tied to the lifetime of the array it was built from,
+/// so it can be kept in long lived structs or sent across threads.
+/// The values themselves may still borrow from elsewhere.
+pub type OwnedCartesianTree<T, I = usize> = CartesianTree<'static, T, I, Vec<T>>;
+
+/// How the nodes of a tree are linked to each other. This does
+/// not depend on the values, or on where the tree keeps them.
 #[derive(Debug)]
-pub struct CartesianTree<'a, T: Ord> {
-    nodes: Vec<CartesianTreeNode<'a, T>>,
-    root_idx: Option<CartesianNodeIdx>,
-    action_profile: Vec<CartesianTreeAction>,
+pub(crate) struct Links<I: NodeIndex = usize> {
+    pub(crate) nodes: Nodes<I>,
+    pub(crate) root_idx: Option<CartesianNodeIdx<I>>,
+
+    /// Answers lowest common ancestor queries, and therefore range min
+    /// queries. Building it takes as long as building the tree, and most
+    /// traversals never need it, so we wait for the first query.
+    lca: OnceCell<LowestCommonAncestor<I>>,
+}
+
+impl<I: NodeIndex> Links<I> {
+    pub(crate) fn new(nodes: Nodes<I>, root_idx: Option<CartesianNodeIdx<I>>) -> Self {
+        Links {
+            nodes,
+            root_idx,
+            lca: OnceCell::new(),
+        }
+    }
+
+    pub(crate) fn lca(&self) -> &LowestCommonAncestor<I> {
+        self.lca
+            .get_or_init(|| LowestCommonAncestor::build(&self.nodes, self.root_idx.as_ref()))
+    }
+}
+
+impl<'a, T: Ord> From<&'a [T]> for CartesianTree<'a, T> {
+    fn from(underlying: &'a [T]) -> Self {
+        Self::from_slice_by(underlying, T::cmp)
+    }
+}
+
+impl<T: Ord> From<Vec<T>> for OwnedCartesianTree<T> {
+    fn from(underlying: Vec<T>) -> Self {
+        Self::from_vec_with(underlying, TieBreak::default(), T::cmp)
+    }
+}
+
+impl<T> OwnedCartesianTree<T> {
+    /// Builds a cartesian tree that takes ownership of the underlying
+    /// array. The tree is a min heap according to `cmp`, and resolves values
+    /// that `cmp` considers equal according to `tie_break`.
+    pub fn from_vec_with<F>(underlying: Vec<T>, tie_break: TieBreak, cmp: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        Self::build(underlying, tie_break, cmp)
+    }
+
+    /// Finishes building a tree whose nodes were added one at a time
+    /// with [`CartesianTree::add_node_to_cartesian_tree`]. The stack
+    /// holds the right spine of the tree.
+    pub(crate) fn from_linked_nodes(values: Vec<T>, mut nodes: Nodes, stack: &Stack) -> Self {
+        let root_idx = close_right_spine(&mut nodes, stack);
+        Self::from_links(values, Links::new(nodes, root_idx))
+    }
+}
+
+impl<T, I: NodeIndex> OwnedCartesianTree<T, I> {
+    /// Builds a cartesian tree like [`CartesianTree::from_vec_with`],
+    /// with nodes that refer to each other with indexes of type `I`.
+    /// Returns an error if the array has more values than such indexes
+    /// can address.
+    pub fn try_from_vec_with<F>(
+        underlying: Vec<T>,
+        tie_break: TieBreak,
+        cmp: F,
+    ) -> Result<Self, IndexWidthError>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        check_width::<I>(underlying.len())?;
+        Ok(Self::build(underlying, tie_break, cmp))
+    }
+}
+
+impl<T: Ord, I: NodeIndex> OwnedCartesianTree<T, I> {
+    /// Combines this tree with the tree of the values that come right
+    /// after ours, as if we had built one tree out of all the values.
+    /// Both trees must be min trees with the default [`TieBreak`], as
+    /// built by [`OwnedCartesianTree::from`]. See
+    /// [`OwnedCartesianTree::concat_with`].
+    ///
+    /// ```rust
+    /// use cartesian_tree::OwnedCartesianTree;
+    ///
+    /// let prefix = OwnedCartesianTree::from(vec![93, 84, 33, 64]);
+    /// let tree = prefix.concat(vec![62, 83, 12].into());
+    /// assert_eq!(tree.range_min(3, 5), (4, &62));
+    /// assert_eq!(tree.root().unwrap().array_index(), 6);
+    /// ```
+    pub fn concat(self, other: Self) -> Self {
+        self.concat_with(other, TieBreak::default(), T::cmp)
+    }
+}
+
+impl<T, I: NodeIndex> OwnedCartesianTree<T, I> {
+    /// Combines this tree with the tree of the values that come right
+    /// after ours. We use `cmp` and `tie_break` to compare values across
+    /// the two trees. The trees do not record how they were built, so it is
+    /// up to the caller to pass the `cmp` and `tie_break` that built both
+    /// of them. With any others, the combined tree is not the cartesian
+    /// tree of the combined values, and answers range min queries wrongly.
+    /// Panics if the combined tree has more nodes than indexes of type
+    /// `I` can address.
+    ///
+    /// Only the nodes on the right spine of this tree and the left spine of
+    /// the other change their links. The nodes and values of the other tree
+    /// still move behind ours, with their indexes shifted. Of our nodes, we
+    /// only touch those on our right spine, whose subtrees now end either
+    /// where the other values pop them off the spine, or at the end of the
+    /// other values. So this takes time proportional to the length of the
+    /// other tree plus the length of our right spine. Appending to decreasing
+    /// values touches a single one of our nodes, but increasing values have
+    /// every node on the right spine, so appending them one at a time takes
+    /// quadratic time. As with any tree, the lowest common ancestor
+    /// structure of the combined tree is only built on its first range min
+    /// query, in time linear in the combined length.
+    pub fn concat_with<F>(self, other: Self, tie_break: TieBreak, mut cmp: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let (left_root, right_root) = match (&self.links.root_idx, &other.links.root_idx) {
+            (Some(left_root), Some(right_root)) => (left_root.get(), right_root.get()),
+            (None, _) => return other,
+            (_, None) => return self,
+        };
+        let mid = self.links.nodes.len();
+        if let Err(error) = check_width::<I>(mid + other.links.nodes.len()) {
+            panic!("{}", error);
+        }
+        let mut nodes = self.links.nodes;
+        nodes.extend(other.links.nodes.into_iter().map(|node| node.shifted(mid)));
+        let mut values = self.values;
+        values.extend(other.values);
+
+        let root = stitch(
+            &mut nodes,
+            0,
+            mid,
+            (left_root, mid + right_root),
+            |top, new| tie_break.earlier_wins(cmp(&values[top], &values[new])),
+        );
+        close_outer_spines(&mut nodes, Some(root));
+        let root_idx = Some(CartesianNodeIdx::new(root));
+        Self::from_links(values, Links::new(nodes, root_idx))
+    }
+}
+
+impl OwnedCartesianTree<usize> {
+    /// Rebuilds the tree whose cartesian tree number is `number`. This
+    /// is the inverse of [`CartesianTree::signature`]. Since many arrays
+    /// share a shape, we pick the depths of the nodes as a canonical
+    /// array with that shape. The minimum of every range of this array is
+    /// unique, so the array has the same shape whichever [`TieBreak`]
+    /// policy we use on it. Returns `None` if `number` is not the number
+    /// of any tree, that is if one of its pops would empty the stack.
+    /// The rebuilt tree always has the default `usize` indexes.
+    pub fn from_cartesian_tree_number(number: &CartesianTreeNumber) -> Option<Self> {
+        // When we replay the actions, each push finds the stack
+        // holding exactly the nodes that stay its ancestors.
+        let heights = number.push_heights()?;
+        let (nodes, root_idx) =
+            Self::link_nodes(heights.len(), |top, new| heights[top] < heights[new]);
+        let links = Links::new(nodes, root_idx);
+        let depths = (0..links.nodes.len())
+            .map(|i| links.lca().depth(i))
+            .collect();
+        Some(Self::from_links(depths, links))
+    }
+}
+
+/// Once we have added every node, the nodes left on the right spine have
+/// subtrees that extend all the way to the end of the array. The bottom
+/// of the stack is the root of the tree.
+fn close_right_spine<I: NodeIndex>(
+    nodes: &mut Nodes<I>,
+    stack: &Stack<I>,
+) -> Option<CartesianNodeIdx<I>> {
+    let len = nodes.len();
+    for node_idx in stack {
+        nodes[node_idx.clone()].set_span_end(len);
+    }
+    stack.first().cloned()
+}
+
+/// Stitches two adjacent trees together, and returns the root of the
+/// combined tree. The first of `nodes` is the node at index `offset`; the
+/// left tree holds the nodes before `mid`, and the right tree the rest.
+///
+/// Had we built the combined tree in one go, the stack would hold the right
+/// spine of the left tree right before we add `mid`. The only nodes of the
+/// right tree that ever pop a node of the left tree are those on its left
+/// spine, since every other node has an earlier ancestor in the right tree
+/// that stays on the stack above the left tree. So we add just those nodes,
+/// from the bottom up, with the right spine of the left tree on the stack.
+/// Instead of keeping an explicit stack, we walk up that spine through
+/// parent pointers. This only touches the nodes on the two spines. If we
+/// pop the whole left tree, the subtrees of the nodes further up the left
+/// spine of the right tree now start at `offset`, so we extend those too.
+/// The subtrees of the nodes on the right spine of the combined tree may
+/// still end too early, which [`close_outer_spines`] finishes.
+pub(crate) fn stitch<I: NodeIndex, F>(
+    nodes: &mut [CartesianTreeNode<I>],
+    offset: usize,
+    mid: usize,
+    (left_root, right_root): (usize, usize),
+    mut stays_ancestor: F,
+) -> usize
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    // The last node of the left tree is the bottom of its right spine,
+    // and the first node of the right tree the bottom of its left spine
+    let mut top = Some(mid - 1);
+    let mut cur = mid;
+    loop {
+        let next = nodes[cur - offset]
+            .parent_idx
+            .as_ref()
+            .map(|parent| parent.get());
+        let mut last_popped = None;
+        while let Some(popped) = top.filter(|&top| !stays_ancestor(top, cur)) {
+            nodes[popped - offset].set_span_end(cur);
+            top = nodes[popped - offset]
+                .parent_idx
+                .as_ref()
+                .map(|parent| parent.get());
+            last_popped = Some(popped);
+        }
+
+        // The last node we popped from the left tree replaces the
+        // left child that `cur` has in the right tree, if any
+        if let Some(popped) = last_popped {
+            nodes[cur - offset].left_child_idx = Some(CartesianNodeIdx::new(popped));
+        }
+        nodes[cur - offset].set_span_start(cur);
+        if let Some(left) = nodes[cur - offset].left_child_idx.clone() {
+            nodes[left.get() - offset].parent_idx = Some(CartesianNodeIdx::new(cur));
+            let span_start = nodes[left.get() - offset].span().start;
+            nodes[cur - offset].set_span_start(span_start);
+        }
+
+        match top {
+            // We popped the whole left tree, so the nodes further up
+            // the left spine of the right tree keep their places, but
+            // their subtrees now take in the whole left tree
+            None => {
+                let mut above = next;
+                while let Some(idx) = above {
+                    nodes[idx - offset].set_span_start(offset);
+                    above = nodes[idx - offset]
+                        .parent_idx
+                        .as_ref()
+                        .map(|parent| parent.get());
+                }
+                return right_root;
+            }
+            Some(top) => {
+                nodes[top - offset].right_child_idx = Some(CartesianNodeIdx::new(cur));
+                nodes[cur - offset].parent_idx = Some(CartesianNodeIdx::new(top));
+            }
+        }
+        match next {
+            Some(next) => cur = next,
+            None => return left_root,
+        }
+    }
+}
+
+/// Once a tree has been stitched together out of smaller ones, the
+/// subtrees of the nodes on its outer spines may not reach the
+/// ends of the array yet. This extends them. The nodes whose subtrees
+/// already reach the ends sit below those that do not, so we stop at
+/// the first of them, and only touch the nodes whose spans change.
+pub(crate) fn close_outer_spines<I: NodeIndex>(
+    nodes: &mut [CartesianTreeNode<I>],
+    root: Option<usize>,
+) {
+    let len = nodes.len();
+    let mut cur = root;
+    while let Some(idx) = cur.filter(|&idx| nodes[idx].span().start != 0) {
+        nodes[idx].set_span_start(0);
+        cur = nodes[idx].left_child_idx.as_ref().map(|left| left.get());
+    }
+    let mut cur = root;
+    while let Some(idx) = cur.filter(|&idx| nodes[idx].span().end != len) {
+        nodes[idx].set_span_end(len);
+        cur = nodes[idx].right_child_idx.as_ref().map(|right| right.get());
+    }
+}
+
+impl<'a, T: Ord> CartesianTree<'a, T> {
+    /// Builds a cartesian tree that is a max heap rather than a
+    /// min heap. Range min queries on this tree therefore
+    /// return the maximum value in the range.
+    pub fn max_from(underlying: &'a [T]) -> Self {
+        Self::from_slice_by(underlying, |a, b| b.cmp(a))
+    }
+
+    /// Builds a min heap cartesian tree that resolves duplicate
+    /// values according to the given policy.
+    pub fn with_tie_break(underlying: &'a [T], tie_break: TieBreak) -> Self {
+        Self::from_slice_with(underlying, tie_break, T::cmp)
+    }
 }
 
 // To create the cartesian tree, we pop the stack until either
@@ -68,63 +487,276 @@ pub struct CartesianTree<'a, T: Ord> {
 // Once we break out of the `pop` loop, we make the item we popped
 // a left child of the new item we are adding. Additionally, we make
 // this new item a right/left child of the item atop the stack
-impl<'a, T: Ord> From<&'a [T]> for CartesianTree<'a, T> {
-    fn from(underlying: &'a [T]) -> Self {
-        let len = underlying.len();
-        let mut nodes = Vec::with_capacity(len);
-        let mut stack = Vec::<CartesianNodeIdx>::with_capacity(len);
-        let mut action_profile = Vec::with_capacity(len * 2);
-        for (idx, value) in underlying.iter().enumerate() {
-            nodes.push(value.into());
-            let node_idx = CartesianNodeIdx(idx);
-            Self::add_node_to_cartesian_tree(&mut nodes, &mut stack, &mut action_profile, node_idx);
-        }
-        let root_idx = stack.first().map(|min| min.clone());
+impl<'a, T> CartesianTree<'a, T> {
+    /// Builds a cartesian tree in which every node is smaller than its
+    /// descendants according to the given comparison function. The
+    /// minimum found by [`CartesianTree::range_min`] is the smallest
+    /// value according to `cmp`.
+    pub fn from_slice_by<F>(underlying: &'a [T], cmp: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        Self::from_slice_with(underlying, TieBreak::default(), cmp)
+    }
+
+    /// Builds a cartesian tree that is a min heap according to `cmp`, and
+    /// that resolves values that `cmp` considers equal according to
+    /// `tie_break`. This is the most general way of building a tree.
+    pub fn from_slice_with<F>(underlying: &'a [T], tie_break: TieBreak, cmp: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        Self::build(underlying, tie_break, cmp)
+    }
+
+    /// Builds a cartesian tree that is a min heap with respect to the
+    /// keys extracted from the values by `key_fn`.
+    pub fn from_slice_by_key<K, F>(underlying: &'a [T], mut key_fn: F) -> Self
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        Self::from_slice_by(underlying, |a, b| key_fn(a).cmp(&key_fn(b)))
+    }
+}
+
+impl<'a, T, I: NodeIndex> CartesianTree<'a, T, I> {
+    /// Builds a cartesian tree like [`CartesianTree::from_slice_with`],
+    /// with nodes that refer to each other with indexes of type `I`.
+    /// Returns an error if the array has more values than such indexes
+    /// can address.
+    ///
+    /// ```rust
+    /// use cartesian_tree::CartesianTree;
+    /// use cartesian_tree::TieBreak;
+    ///
+    /// let v = [93, 84, 33, 64, 62, 83, 63];
+    /// let tree = CartesianTree::<_, u32>::try_from_slice_with(&v, TieBreak::default(), i32::cmp);
+    /// assert_eq!(tree.unwrap().range_min(3, 6), (4, &62));
+    /// ```
+    pub fn try_from_slice_with<F>(
+        underlying: &'a [T],
+        tie_break: TieBreak,
+        cmp: F,
+    ) -> Result<Self, IndexWidthError>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        check_width::<I>(underlying.len())?;
+        Ok(Self::build(underlying, tie_break, cmp))
+    }
+}
+
+impl<'a, T, I: NodeIndex, V: AsRef<[T]>> CartesianTree<'a, T, I, V> {
+    /// Builds the tree. Indexes of type `I` must be able to
+    /// address every value of the array.
+    fn build<F>(values: V, tie_break: TieBreak, mut cmp: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let slice = values.as_ref();
+        let (nodes, root_idx) = Self::link_nodes(slice.len(), |top, new| {
+            tie_break.earlier_wins(cmp(&slice[top], &slice[new]))
+        });
+        Self::from_links(values, Links::new(nodes, root_idx))
+    }
+
+    /// Puts a tree together out of its values and the links between its nodes
+    pub(crate) fn from_links(values: V, links: Links<I>) -> Self {
         CartesianTree {
-            nodes,
-            root_idx,
-            action_profile,
+            values,
+            links,
+            values_type: PhantomData,
+            array_lifetime: PhantomData,
         }
     }
-}
 
-impl<'a, T: Ord> CartesianTree<'a, T> {
-    pub fn in_order_traversal(&self) -> Vec<&T> {
-        let mut res = Vec::with_capacity(self.nodes.len());
-        self.traversal_helper(&self.root_idx, &mut res);
-        res
-    }
-
-    fn traversal_helper(&self, cur_idx: &Option<CartesianNodeIdx>, res: &mut Vec<&'a T>) {
-        let nodes = &self.nodes;
-        match cur_idx {
-            None => {}
-            Some(cur_sub_root) => {
-                self.traversal_helper(&nodes[cur_sub_root.clone()].left_child_idx, res);
-                res.push(&nodes[cur_sub_root.clone()].value);
-                self.traversal_helper(&nodes[cur_sub_root.clone()].right_child_idx, res);
-            }
+    /// Wires up the nodes of a tree with `len` nodes. `stays_ancestor`
+    /// decides whether the node atop the stack stays an ancestor of
+    /// the node we are adding. Returns the nodes and the root.
+    pub(crate) fn link_nodes<F>(
+        len: usize,
+        mut stays_ancestor: F,
+    ) -> (Nodes<I>, Option<CartesianNodeIdx<I>>)
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let mut nodes = vec![CartesianTreeNode::default(); len];
+        let mut stack = Vec::with_capacity(len);
+        for idx in 0..len {
+            let node_idx = CartesianNodeIdx::new(idx);
+            Self::add_node_to_cartesian_tree(&mut nodes, &mut stack, node_idx, &mut stays_ancestor);
+        }
+        let root_idx = close_right_spine(&mut nodes, &stack);
+        (nodes, root_idx)
+    }
+
+    /// Converts this tree into one that owns its underlying array,
+    /// copying the array if the tree borrows it. The shape of the
+    /// tree is kept as is.
+    pub fn into_owned(self) -> OwnedCartesianTree<T, I>
+    where
+        V: Into<Vec<T>>,
+    {
+        CartesianTree::from_links(self.values.into(), self.links)
+    }
+
+    /// The array from which this tree was built
+    pub fn values(&self) -> &[T] {
+        self.values.as_ref()
+    }
+
+    /// A handle to the root of the tree, which is the minimum of
+    /// the whole array. This is `None` when the array is empty.
+    pub fn root(&self) -> Option<NodeRef<'_, T, I>> {
+        self.links
+            .root_idx
+            .as_ref()
+            .map(|root| NodeRef::new(self.values(), &self.links, root.get()))
+    }
+
+    /// A handle to the node that represents the value at
+    /// index `i` of the underlying array, if there is one.
+    pub fn node(&self, i: usize) -> Option<NodeRef<'_, T, I>> {
+        if i < self.links.nodes.len() {
+            Some(NodeRef::new(self.values(), &self.links, i))
+        } else {
+            None
         }
     }
 
-    /// Calculates the cartesian tree number of this tree
-    /// using the sequence of `push` and `pop` operations
-    /// stored in the `action_profile`. Note that calculating this
-    /// value only makes sense when the underlying array is small.
-    /// More specifically, this procedure assumes that the underlying
-    /// array has at most 32 items. This makes sense in our context
-    /// since we're mostly interested in the cartesian tree numbers
-    /// of RMQ blocks
+    pub fn in_order_traversal(&self) -> Vec<&T> {
+        self.iter_in_order().map(|(_, value)| value).collect()
+    }
+
+    /// Lazily walks the tree in order, yielding the index of each node
+    /// along with its value. This yields the underlying array.
+    pub fn iter_in_order(&self) -> InOrder<'_, T, I> {
+        InOrder::new(self.values(), &self.links)
+    }
+
+    /// Lazily walks the tree in pre-order, visiting each node
+    /// before the nodes in its subtrees.
+    pub fn iter_pre_order(&self) -> PreOrder<'_, T, I> {
+        PreOrder::new(self.values(), &self.links)
+    }
+
+    /// Lazily walks the tree in post-order, visiting each node
+    /// after the nodes in its subtrees.
+    pub fn iter_post_order(&self) -> PostOrder<'_, T, I> {
+        PostOrder::new(self.values(), &self.links)
+    }
+
+    /// Lazily walks the tree one level at a time, starting at the root.
+    pub fn iter_level_order(&self) -> LevelOrder<'_, T, I> {
+        LevelOrder::new(self.values(), &self.links)
+    }
+
+    /// Finds the minimum value in the inclusive range `[i, j]` of the
+    /// underlying array. Since the tree is a min heap whose in-order
+    /// traversal is the array, that minimum is the lowest common ancestor
+    /// of the nodes at `i` and `j`. Returns the index of the minimum
+    /// along with a reference to it. The minimum is relative to the ordering
+    /// the tree was built with, so on a tree built with
+    /// [`CartesianTree::max_from`] this finds the maximum.
+    ///
+    /// Panics if either `i` or `j` is out of bounds.
+    pub fn range_min(&self, i: usize, j: usize) -> (usize, &T) {
+        let min_idx = self.lca(i, j);
+        (min_idx, &self.values()[min_idx])
+    }
+
+    /// Finds the lowest common ancestor of the nodes at indexes `i`
+    /// and `j` of the underlying array, and returns its index. This
+    /// takes constant time, except that the first query on a tree
+    /// builds the lowest common ancestor structure, in linear time.
+    ///
+    /// Panics if either `i` or `j` is out of bounds.
+    pub fn lca(&self, i: usize, j: usize) -> usize {
+        let len = self.links.nodes.len();
+        assert!(
+            i < len && j < len,
+            "nodes {} and {} are out of bounds for a tree with {} nodes",
+            i,
+            j,
+            len
+        );
+        self.links.lca().query(i, j)
+    }
+
+    /// The maximal range of the underlying array in which the value at
+    /// index `i` is the minimum. This is the range spanned by the subtree
+    /// rooted at `i`, which we record during construction, so this is a
+    /// constant time lookup. Equal values are resolved according to the
+    /// [`TieBreak`] policy the tree was built with: with the default
+    /// policy, the range stops right before the next value that is at
+    /// most `A[i]`, but extends past equal values to the left.
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn min_span(&self, i: usize) -> Range<usize> {
+        self.links.nodes[i].span()
+    }
+
+    /// Lazily yields, for every index of the underlying array in
+    /// order, the maximal range in which its value is the minimum.
+    /// See [`CartesianTree::min_span`] for how we treat equal values.
+    pub fn min_spans(&self) -> MinSpans<'_, T, I> {
+        MinSpans::new(self.values(), &self.links)
+    }
+
+    /// The depth of the node at index `i` of the underlying
+    /// array. The root of the tree has depth 0. Like
+    /// [`CartesianTree::lca`], this needs the lowest common
+    /// ancestor structure, which the first query builds.
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn depth(&self, i: usize) -> usize {
+        let len = self.links.nodes.len();
+        assert!(
+            i < len,
+            "node {} is out of bounds for a tree with {} nodes",
+            i,
+            len
+        );
+        self.links.lca().depth(i)
+    }
+
+    /// Calculates the cartesian tree number of this tree. This fits in a
+    /// `u64` only when the underlying array is small. More specifically,
+    /// the array should have at most 32 items. This makes sense in our
+    /// context since we're mostly interested in the cartesian tree numbers
+    /// of RMQ blocks. Use [`CartesianTree::signature`] for larger arrays.
+    ///
+    /// Panics if the number does not fit in a `u64`.
     pub fn cartesian_tree_number(&self) -> u64 {
-        let mut number = 0;
-        let mut offset = 0;
-        for action in &self.action_profile {
-            if action == &CartesianTreeAction::Push {
-                number |= 1 << offset;
+        self.signature().to_u64().unwrap_or_else(|| {
+            panic!(
+                "the cartesian tree number of an array of {} items does not fit in a u64",
+                self.links.nodes.len()
+            )
+        })
+    }
+
+    /// Calculates the cartesian tree number of this tree, however
+    /// large the underlying array is. Two trees have the same
+    /// signature exactly when they have the same shape.
+    ///
+    /// We replay the `push` and `pop` operations of the construction from
+    /// the shape of the tree. The nodes that the node at `i` pops off the
+    /// stack are exactly the right spine of its left subtree, since that
+    /// spine was atop the stack right before `i` arrived.
+    pub fn signature(&self) -> CartesianTreeNumber {
+        let mut number = NumberBuilder::default();
+        let nodes = &self.links.nodes;
+        for node in nodes {
+            let mut popped = &node.left_child_idx;
+            while let Some(idx) = popped {
+                number.push_action(false);
+                popped = &nodes[idx.get()].right_child_idx;
             }
-            offset += 1;
+            number.push_action(true);
         }
-        number
+        number.finish()
     }
 
     /// Adds the node at the given idx into the tree by wiring up the
@@ -132,12 +764,14 @@ impl<'a, T: Ord> CartesianTree<'a, T> {
     /// node has already been added to `nodes` the list of nodes.
     /// This procedure returns an optional index value
     /// that is populated if the root changed.
-    fn add_node_to_cartesian_tree(
-        nodes: &mut Nodes<T>,
-        stack: &mut Stack,
-        actions: &mut Actions,
-        new_idx: CartesianNodeIdx,
-    ) -> () {
+    pub(crate) fn add_node_to_cartesian_tree<F>(
+        nodes: &mut Nodes<I>,
+        stack: &mut Stack<I>,
+        new_idx: CartesianNodeIdx<I>,
+        mut stays_ancestor: F,
+    ) where
+        F: FnMut(usize, usize) -> bool,
+    {
         let mut last_popped = None;
         loop {
             match stack.last() {
@@ -145,34 +779,73 @@ impl<'a, T: Ord> CartesianTree<'a, T> {
                 Some(top_node_idx) => {
                     // If the new node is greater than the value atop the stack,
                     // we make the new node a right child of that value
-                    if nodes[top_node_idx.clone()].value < nodes[new_idx.clone()].value {
+                    if stays_ancestor(top_node_idx.get(), new_idx.get()) {
                         nodes[top_node_idx.clone()].right_child_idx = Some(new_idx.clone());
+                        nodes[new_idx.clone()].parent_idx = Some(top_node_idx.clone());
                         break;
                     }
+                    // The subtree of a node is complete once it leaves the right spine
+                    nodes[top_node_idx.clone()].set_span_end(new_idx.get());
                     last_popped = stack.pop();
-                    actions.push(CartesianTreeAction::Pop);
                 }
             }
         }
         // We make the last item we popped a left child of the
-        // new node
+        // new node. The new node's subtree starts where that of
+        // its left child does.
+        nodes[new_idx.clone()].set_span_start(new_idx.get());
         if let Some(last_popped_idx) = last_popped {
+            let span_start = nodes[last_popped_idx.clone()].span().start;
+            nodes[new_idx.clone()].set_span_start(span_start);
+            nodes[last_popped_idx.clone()].parent_idx = Some(new_idx.clone());
             nodes[new_idx.clone()].left_child_idx = Some(last_popped_idx);
         }
         stack.push(new_idx);
-        actions.push(CartesianTreeAction::Push);
     }
 }
 
+/// Calculates the cartesian tree number of a small block without
+/// materializing its tree. We run the same stack based procedure as
+/// [`CartesianTree::from`], but only keep track of the values on the
+/// right spine. As with [`CartesianTree::cartesian_tree_number`], the
+/// block should have at most 32 items.
+pub(crate) fn cartesian_tree_number_of<T: Ord>(block: &[T], tie_break: TieBreak) -> u64 {
+    let mut number = 0;
+    let mut offset = 0;
+    let mut spine = ShapeStack::new(tie_break);
+    for value in block {
+        offset += spine.push(value);
+        number |= 1 << offset;
+        offset += 1;
+    }
+    number
+}
+
 #[test]
 fn test_cartesian_tree() {
     use pretty_assertions::assert_eq;
 
     let v = [93, 84, 33, 64, 62, 83, 63];
     let tree: CartesianTree<'_, _> = v.as_ref().into();
-    assert!(tree.root_idx.is_some());
-    assert_eq!(tree.nodes[tree.root_idx.clone().unwrap()].value, &33);
+    let root_idx = tree.links.root_idx.clone();
+    assert_eq!(tree.values[root_idx.unwrap().get()], 33);
     for (&l, &r) in tree.in_order_traversal().into_iter().zip(v.iter()) {
         assert_eq!(l, r);
     }
+
+    assert_eq!(
+        tree.cartesian_tree_number(),
+        cartesian_tree_number_of(v.as_ref(), TieBreak::Rightmost)
+    );
+}
+
+#[test]
+fn test_lca_is_built_on_first_query() {
+    let v = [93, 84, 33, 64, 62, 83, 63];
+    let tree = CartesianTree::from(&v[..]);
+    assert_eq!(tree.iter_pre_order().count(), v.len());
+    assert_eq!(tree.min_span(4), 3..7);
+    assert!(tree.links.lca.get().is_none());
+    assert_eq!(tree.range_min(3, 6), (4, &62));
+    assert!(tree.links.lca.get().is_some());
 }